@@ -0,0 +1,165 @@
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::{
+    diff::ProductDiff,
+    entities::{attribute::Attribute, product::Product, upload_result::Status},
+};
+
+/// Products a bulk edit applies to, every given criterion must match
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct Selector {
+    pub status: Option<Status>,
+    /// Ignoring case
+    pub category: Option<String>,
+    #[serde(default)]
+    pub skus: Vec<String>,
+    pub sku_prefix: Option<String>,
+}
+
+impl Selector {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn matches(&self, product: &Product, status: Status) -> bool {
+        self.status.is_none_or(|s| s == status)
+            && self.category.as_ref().is_none_or(|c| c.eq_ignore_ascii_case(product.category()))
+            && (self.skus.is_empty() || self.skus.contains(product.sku()))
+            && self.sku_prefix.as_ref().is_none_or(|p| product.sku().starts_with(p.as_str()))
+    }
+}
+
+/// Text field of the product that can be set
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Title,
+    Brand,
+    Category,
+    Description,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    SetField { field: Field, value: String },
+    /// Products that already have the attribute keep their value
+    AddAttribute { attribute: Attribute },
+    RemoveAttribute { code: String },
+    /// Only products that have the attribute are changed
+    ReplaceAttribute { attribute: Attribute },
+    ReplaceInDescription { find: String, replace: String },
+    AddImage { url: String },
+    RemoveImage { url: String },
+}
+
+impl Operation {
+    pub fn apply(&self, product: &mut Product) {
+        match self {
+            Operation::SetField { field, value } => match field {
+                Field::Title => product.set_title(value.clone()),
+                Field::Brand => product.set_brand(value.clone()),
+                Field::Category => product.set_category(value.clone()),
+                Field::Description => product.set_description(value.clone()),
+            },
+            Operation::AddAttribute { attribute } => {
+                if !product.attributes().iter().any(|a| a.code == attribute.code) {
+                    product.attributes_mut().push(attribute.clone());
+                }
+            }
+            Operation::RemoveAttribute { code } => product.attributes_mut().retain(|a| a.code != *code),
+            Operation::ReplaceAttribute { attribute } => {
+                for existing in product.attributes_mut().iter_mut().filter(|a| a.code == attribute.code) {
+                    existing.value = attribute.value.clone();
+                }
+            }
+            Operation::ReplaceInDescription { find, replace } => {
+                if !find.is_empty() {
+                    let description = product.description().replace(find.as_str(), replace);
+                    product.set_description(description);
+                }
+            }
+            Operation::AddImage { url } => {
+                product.add_image(url.clone());
+            }
+            Operation::RemoveImage { url } => {
+                product.remove_image(url);
+            }
+        }
+    }
+}
+
+/// Applies the operations in order
+pub fn apply(product: &Product, operations: &[Operation]) -> Product {
+    let mut edited = product.clone();
+    operations.iter().for_each(|operation| operation.apply(&mut edited));
+    edited
+}
+
+/// Edited products are uploaded as new versions, the stored ones keep what Kaspi received
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct BulkEdit {
+    pub selector: Selector,
+    pub operations: Vec<Operation>,
+}
+
+/// Product the edit changes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct BulkChange {
+    pub id: Uuid,
+    pub sku: String,
+    pub diff: ProductDiff,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::testing::product_json;
+
+    fn product() -> Product {
+        let mut product = product_json("LACEFRONT-27", "Title");
+        product["description"] = json!("Made by Pariki Almaty");
+        product["attributes"] = json!([{ "code": "color", "value": "black" }]);
+        product["images"] = json!([{ "url": "https://a.kz/1.jpg" }]);
+
+        serde_json::from_value(product).unwrap()
+    }
+
+    #[test]
+    fn select() {
+        let selector: Selector = serde_json::from_value(json!({ "category": "pariki", "sku_prefix": "LACE" })).unwrap();
+
+        assert!(selector.matches(&product(), Status::FINISHED));
+        assert!(!Selector { status: Some(Status::ABORTED), ..selector.clone() }.matches(&product(), Status::FINISHED));
+        assert!(!Selector { skus: vec![String::from("OTHER")], ..selector }.matches(&product(), Status::FINISHED));
+        assert!(Selector::default().is_empty());
+    }
+
+    #[test]
+    fn apply_operations() {
+        let operations: Vec<Operation> = serde_json::from_value(json!([
+            { "op": "set_field", "field": "brand", "value": "Pariki" },
+            { "op": "add_attribute", "attribute": { "code": "synthetic", "value": true } },
+            { "op": "add_attribute", "attribute": { "code": "color", "value": "red" } },
+            { "op": "replace_attribute", "attribute": { "code": "length", "value": "60" } },
+            { "op": "replace_in_description", "find": "Pariki Almaty", "replace": "Pariki" },
+            { "op": "remove_image", "url": "https://a.kz/1.jpg" },
+            { "op": "add_image", "url": "https://a.kz/2.jpg" }
+        ])).unwrap();
+
+        let edited = apply(&product(), &operations);
+
+        assert_eq!(json!(edited), json!({
+            "sku": "LACEFRONT-27",
+            "title": "Title",
+            "brand": "Pariki",
+            "category": "Pariki",
+            "description": "Made by Pariki",
+            "attributes": [{ "code": "color", "value": "black" }, { "code": "synthetic", "value": true }],
+            "images": [{ "url": "https://a.kz/2.jpg" }]
+        }));
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use tokio::sync::Mutex;
+use crate::{
+    retry,
+    translit::transliterate,
+    entities::{attribute::AttributeValue, product::Product},
+};
+
+pub const DEFAULT_VALUES_URL: &str = "https://kaspi.kz/shop/api/products/classification/attribute/values";
+/// Number of close values suggested for an unresolved one
+pub const SUGGESTIONS: usize = 3;
+
+/// Value Kaspi accepts for an enumerated attribute
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct AllowedValue {
+    pub code: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct ResolvedValue {
+    pub code: String,
+    pub input: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct UnresolvedValue {
+    pub code: String,
+    pub input: String,
+    /// Closest allowed values, best first
+    pub suggestions: Vec<String>,
+}
+
+/// Attribute values of one product mapped to the allowed ones
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct AttributeReport {
+    pub sku: String,
+    /// Values replaced with allowed ones
+    pub resolved: Vec<ResolvedValue>,
+    pub unresolved: Vec<UnresolvedValue>,
+}
+
+impl AttributeReport {
+    pub fn is_ok(&self) -> bool {
+        self.unresolved.is_empty()
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.is_ok() && self.resolved.is_empty()
+    }
+}
+
+/// Allowed values of the enumerated attributes, cached per category and attribute
+pub struct Dictionaries {
+    url: String,
+    cache: Mutex<HashMap<(String, String), Vec<AllowedValue>>>,
+}
+
+impl Dictionaries {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reads KASPI_ATTRIBUTE_VALUES_URL
+    pub fn from_env() -> Self {
+        Self::new(&dotenv::var("KASPI_ATTRIBUTE_VALUES_URL").unwrap_or_else(|_| DEFAULT_VALUES_URL.to_owned()))
+    }
+
+    /// Returns the allowed values of the attribute, asking Kaspi the first time
+    /// Attributes taking free text have none
+    pub async fn values(&self, client: &Client, category: &str, code: &str) -> Result<Vec<AllowedValue>, String> {
+        let key = (category.to_owned(), code.to_owned());
+        if let Some(values) = self.cache.lock().await.get(&key) {
+            return Ok(values.clone());
+        }
+
+        let response = retry::send("attribute_values", || {
+            client
+                .get(&self.url)
+                .query(&[("c", category), ("a", code)])
+        }, true).await?;
+
+        let values = response.json::<Vec<AllowedValue>>()
+            .await
+            .map_err(|e| format!("Could not parse values of {}: {}", code, e))?;
+
+        self.cache.lock().await.insert(key, values.clone());
+        Ok(values)
+    }
+
+    /// Replaces free-text values of enumerated attributes with the allowed ones
+    /// Attributes whose values could not be fetched are left as they are
+    pub async fn normalize(&self, client: &Client, product: &mut Product) -> AttributeReport {
+        let mut report = AttributeReport { sku: product.sku().to_owned(), ..AttributeReport::default() };
+        let category = product.category().to_owned();
+
+        for attribute in product.attributes_mut().iter_mut() {
+            let AttributeValue::String(input) = &attribute.value else {
+                continue;
+            };
+
+            let values = match self.values(client, &category, &attribute.code).await {
+                Ok(values) if !values.is_empty() => values,
+                Ok(_) => continue,
+                Err(e) => {
+                    log::warn!("Could not fetch values of {}: {}", attribute.code, e);
+                    continue;
+                }
+            };
+
+            match resolve(input, &values) {
+                Ok(value) if value.code == *input => {}
+                Ok(value) => {
+                    report.resolved.push(ResolvedValue {
+                        code: attribute.code.clone(),
+                        input: input.clone(),
+                        value: value.code.clone(),
+                    });
+                    attribute.value = AttributeValue::String(value.code.clone());
+                }
+                Err(suggestions) => report.unresolved.push(UnresolvedValue {
+                    code: attribute.code.clone(),
+                    input: input.clone(),
+                    suggestions: suggestions.into_iter().map(|v| v.code.clone()).collect(),
+                }),
+            }
+        }
+
+        report
+    }
+}
+
+/// Finds the allowed value meant by the input
+/// Tries the code, then the name ignoring case, then the transliterated input,
+/// then the only value within a few typos
+/// Otherwise, returns the closest values
+pub fn resolve<'a>(input: &str, values: &'a [AllowedValue]) -> Result<&'a AllowedValue, Vec<&'a AllowedValue>> {
+    if let Some(value) = values.iter().find(|v| v.code == input) {
+        return Ok(value);
+    }
+
+    let input = input.trim().to_lowercase();
+    let latin = transliterate(&input);
+
+    if let Some(value) = values.iter().find(|v| {
+        let code = v.code.to_lowercase();
+        code == input || code == latin || v.name.to_lowercase() == input
+    }) {
+        return Ok(value);
+    }
+
+    let mut ranked: Vec<(usize, &AllowedValue)> = values
+        .iter()
+        .map(|v| {
+            let distance = strsim::levenshtein(&latin, &v.code.to_lowercase())
+                .min(strsim::levenshtein(&input, &v.name.to_lowercase()));
+            (distance, v)
+        })
+        .collect();
+    ranked.sort_by_key(|(distance, _)| *distance);
+
+    let allowed = (latin.chars().count() / 5).max(1);
+    match ranked.as_slice() {
+        [(best, value), rest @ ..] if *best <= allowed && rest.first().is_none_or(|(next, _)| next > best) => Ok(value),
+        _ => Err(ranked.into_iter().take(SUGGESTIONS).map(|(_, v)| v).collect()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, HttpResponse};
+    use crate::testing::{product_json, serve};
+
+    fn values() -> Vec<AllowedValue> {
+        [("zhenskiy", "Женский"), ("muzhskoy", "Мужской"), ("uniseks", "Унисекс")]
+            .into_iter()
+            .map(|(code, name)| AllowedValue { code: code.to_owned(), name: name.to_owned() })
+            .collect()
+    }
+
+    #[test]
+    fn resolve_free_text() {
+        let values = values();
+        let code = |input: &str| resolve(input, &values).map(|v| v.code.as_str()).map_err(|s| s.len());
+
+        assert_eq!(code("zhenskiy"), Ok("zhenskiy"));
+        assert_eq!(code("Женский"), Ok("zhenskiy"));
+        assert_eq!(code("женский "), Ok("zhenskiy"));
+        assert_eq!(code("ZHENSKIY"), Ok("zhenskiy"));
+        assert_eq!(code("мужкой"), Ok("muzhskoy"));
+        assert_eq!(code("детский"), Err(SUGGESTIONS));
+    }
+
+    #[actix_rt::test]
+    async fn normalize_product() {
+        let (base_url, server) = serve(|config| {
+            config.route("/values", web::get().to(|query: web::Query<HashMap<String, String>>| async move {
+                if query["a"] == "purpose" {
+                    HttpResponse::Ok().json(values())
+                } else {
+                    HttpResponse::Ok().json(Vec::<AllowedValue>::new())
+                }
+            }));
+        });
+
+        let mut product = product_json("LACEFRONT-27", "Title");
+        product["attributes"] = serde_json::json!([
+            { "code": "purpose", "value": "Женский" },
+            { "code": "length", "value": "60 см" },
+            { "code": "synthetic", "value": true }
+        ]);
+        let mut product: Product = serde_json::from_value(product).unwrap();
+
+        let dictionaries = Dictionaries::new(&format!("{}/values", base_url));
+        let report = dictionaries.normalize(&Client::new(), &mut product).await;
+
+        assert!(report.is_ok());
+        assert_eq!(report.resolved, vec![ResolvedValue {
+            code: String::from("purpose"),
+            input: String::from("Женский"),
+            value: String::from("zhenskiy"),
+        }]);
+        assert_eq!(product.attributes()[0].value, AttributeValue::String(String::from("zhenskiy")));
+        assert_eq!(product.attributes()[1].value, AttributeValue::String(String::from("60 см")));
+
+        server.stop(false).await;
+    }
+}
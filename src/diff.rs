@@ -0,0 +1,152 @@
+use std::{collections::HashSet, hash::Hash};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::entities::{attribute::Attribute, product::Product};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// Items of an unordered list that one version has and the other has not
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+#[aliases(AttributeChanges = SetChange<Attribute>, ImageChanges = SetChange<String>)]
+pub struct SetChange<T> {
+    pub added: Vec<T>,
+    pub removed: Vec<T>,
+}
+
+impl<T: Clone + Eq + Hash> SetChange<T> {
+    /// Keeps the order of the versions, ignoring repeated items
+    fn between(old: &[T], new: &[T]) -> Self {
+        let old_set: HashSet<&T> = old.iter().collect();
+        let new_set: HashSet<&T> = new.iter().collect();
+
+        Self {
+            added: unique(new.iter().filter(|item| !old_set.contains(item))),
+            removed: unique(old.iter().filter(|item| !new_set.contains(item))),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+fn unique<'a, T: Clone + Eq + Hash + 'a>(items: impl Iterator<Item = &'a T>) -> Vec<T> {
+    let mut seen = HashSet::new();
+    items.filter(|item| seen.insert(*item)).cloned().collect()
+}
+
+/// Changes between two versions of a product
+/// Reordered attributes or images and whitespace in the texts are not changes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct ProductDiff {
+    pub fields: Vec<FieldChange>,
+    pub attributes: AttributeChanges,
+    pub images: ImageChanges,
+}
+
+impl ProductDiff {
+    pub fn between(old: &Product, new: &Product) -> Self {
+        let texts = [
+            ("sku", old.sku(), new.sku()),
+            ("title", old.title(), new.title()),
+            ("description", old.description(), new.description()),
+            ("brand", old.brand(), new.brand()),
+            ("category", old.category(), new.category()),
+        ];
+
+        let fields = texts
+            .into_iter()
+            .filter(|(_, old, new)| normalize(old) != normalize(new))
+            .map(|(field, old, new)| FieldChange {
+                field: field.to_owned(),
+                old: old.to_owned(),
+                new: new.to_owned(),
+            })
+            .collect();
+
+        let old_images: Vec<String> = old.image_urls().into_iter().cloned().collect();
+        let new_images: Vec<String> = new.image_urls().into_iter().cloned().collect();
+
+        Self {
+            fields,
+            attributes: SetChange::between(old.attributes(), new.attributes()),
+            images: SetChange::between(&old_images, &new_images),
+        }
+    }
+
+    /// Names of the changed fields, with `attributes` and `images` for the lists
+    pub fn changed_fields(&self) -> Vec<String> {
+        let mut fields: Vec<String> = self.fields.iter().map(|f| f.field.clone()).collect();
+        if !self.attributes.is_empty() {
+            fields.push(String::from("attributes"));
+        }
+        if !self.images.is_empty() {
+            fields.push(String::from("images"));
+        }
+        fields
+    }
+
+    /// Whether uploading the new version would change anything on Kaspi
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.attributes.is_empty() && self.images.is_empty()
+    }
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::testing::product_json;
+
+    fn product(title: &str, attributes: serde_json::Value, images: &[&str]) -> Product {
+        let images: Vec<serde_json::Value> = images.iter().map(|url| json!({ "url": url })).collect();
+
+        let mut product = product_json("LACEFRONT-27", title);
+        product["attributes"] = attributes;
+        product["images"] = json!(images);
+
+        serde_json::from_value(product).unwrap()
+    }
+
+    #[test]
+    fn reordering_is_not_a_change() {
+        let attributes = json!([{ "code": "color", "value": "black" }, { "code": "synthetic", "value": true }]);
+        let reordered = json!([{ "code": "synthetic", "value": true }, { "code": "color", "value": "black" }]);
+
+        let old = product("Lace front wig", attributes, &["https://a.kz/1.jpg", "https://a.kz/2.jpg"]);
+        let new = product(" Lace front  wig", reordered, &["https://a.kz/2.jpg", "https://a.kz/1.jpg"]);
+
+        assert!(ProductDiff::between(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn fields_and_sets() {
+        let old = product("Wig", json!([{ "code": "color", "value": "black" }]), &["https://a.kz/1.jpg"]);
+        let new = product("Long wig", json!([{ "code": "color", "value": "red" }]), &["https://a.kz/1.jpg", "https://a.kz/2.jpg"]);
+
+        let diff = ProductDiff::between(&old, &new);
+
+        assert_eq!(diff.fields, vec![FieldChange {
+            field: String::from("title"),
+            old: String::from("Wig"),
+            new: String::from("Long wig"),
+        }]);
+        assert_eq!(json!(diff.attributes), json!({
+            "added": [{ "code": "color", "value": "red" }],
+            "removed": [{ "code": "color", "value": "black" }]
+        }));
+        assert_eq!(diff.images.added, vec![String::from("https://a.kz/2.jpg")]);
+        assert!(diff.images.removed.is_empty());
+        assert!(!diff.is_empty());
+    }
+}
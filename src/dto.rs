@@ -0,0 +1,287 @@
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::{
+    entities::{
+        attempt::Attempt,
+        product::Product,
+        timestamps::Timestamps,
+        upload_result::{Status, UploadResult},
+    },
+    images::ImageReport,
+    dictionary::AttributeReport,
+    bulk::BulkChange,
+    sanitize::DescriptionReport,
+};
+
+/// Body of every error response
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct ErrorBody {
+    /// Id of the record the error is about
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+    pub error: String,
+}
+
+impl ErrorBody {
+    pub fn new(error: impl ToString) -> Self {
+        Self { id: None, error: error.to_string() }
+    }
+
+    pub fn with_id(id: Uuid, error: impl ToString) -> Self {
+        Self { id: Some(id), error: error.to_string() }
+    }
+}
+
+/// Entry of the product list
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct ProductSummary {
+    pub id: Uuid,
+    pub sku: String,
+    pub code: String,
+    pub status: Status,
+    pub timestamps: Timestamps,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct ProductPage {
+    /// Number of products matching the filters
+    pub total: usize,
+    pub page: usize,
+    pub limit: usize,
+    pub items: Vec<ProductSummary>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct ProductDetail {
+    pub id: Uuid,
+    pub code: String,
+    pub status: Status,
+    pub product: Product,
+    pub result: Option<UploadResult>,
+    pub timestamps: Timestamps,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub id: Uuid,
+    pub sku: String,
+    pub title: String,
+    pub score: usize,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct SearchPage {
+    pub total: usize,
+    pub items: Vec<SearchHit>,
+}
+
+/// Attempt of one version of the product
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    /// Id of the version the attempt was made with
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub attempt: Attempt,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct ProductHistory {
+    pub id: Uuid,
+    pub sku: String,
+    pub attempts: Vec<HistoryEntry>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct JobAccepted {
+    pub id: Uuid,
+    /// Warnings about the images, such as dropped duplicates
+    pub images: Vec<ImageReport>,
+    /// Attribute values replaced with the allowed ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<AttributeReport>,
+    /// Descriptions changed by the sanitizer
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub descriptions: Vec<DescriptionReport>,
+}
+
+/// Product of a dry run, as it would be sent to Kaspi
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct DryRunProduct {
+    pub id: Uuid,
+    pub sku: String,
+    /// Exact body of the import request
+    pub body: String,
+    /// Reasons the upload would fail or differ from a new one
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct DryRun {
+    pub products: Vec<DryRunProduct>,
+    pub images: Vec<ImageReport>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<AttributeReport>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub descriptions: Vec<DescriptionReport>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct ImagesRejected {
+    pub images: Vec<ImageReport>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct AttributesRejected {
+    pub attributes: Vec<AttributeReport>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct ExpandError {
+    pub sku: String,
+    pub error: String,
+}
+
+/// Items that could not be built from the template
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct ExpandRejected {
+    pub items: Vec<ExpandError>,
+}
+
+/// Reason the products were not accepted for upload
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum UploadRejected {
+    Images(ImagesRejected),
+    Attributes(AttributesRejected),
+    Items(ExpandRejected),
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct BulkEdited {
+    /// Products the edit changes, by SKU
+    pub products: Vec<BulkChange>,
+    /// False for a preview or a dry run
+    pub applied: bool,
+    /// Upload job of the edited products
+    pub job: Option<Uuid>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct OffersUpdated {
+    pub changed: Vec<String>,
+    /// SKUs whose price and stock were already the same
+    pub unchanged: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct HostedImageError {
+    pub file: String,
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct HostedImages {
+    /// Id of the version the images were added to
+    pub id: Uuid,
+    pub urls: Vec<String>,
+    pub errors: Vec<HostedImageError>,
+    /// New version of the product, with the hosted images
+    pub product: Product,
+    /// Upload job of the new version, None if no image was hosted or on a dry run
+    pub job: Option<Uuid>,
+}
+
+/// Import that Kaspi has not finished, or that was checked before
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct ImportStatus {
+    pub id: Uuid,
+    pub status: Status,
+}
+
+/// Import that has just been archived
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+pub struct ImportResult {
+    pub id: Uuid,
+    pub status: Status,
+    pub result: UploadResult,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ImportCheck {
+    Result(ImportResult),
+    Status(ImportStatus),
+}
+
+/// Entry of a bulk check, failed checks carry the id of the import
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ImportOutcome {
+    Check(ImportCheck),
+    Error(ErrorBody),
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn id() -> Uuid {
+        Uuid::new_v5(&Uuid::NAMESPACE_URL, b"LACEFRONT-27")
+    }
+
+    #[test]
+    fn error_body_shape() {
+        assert_eq!(json!(ErrorBody::new("Invalid id")), json!({ "error": "Invalid id" }));
+        assert_eq!(
+            json!(ErrorBody::with_id(id(), "Product is not found")),
+            json!({ "id": id(), "error": "Product is not found" })
+        );
+    }
+
+    #[test]
+    fn summary_shape() {
+        let summary = ProductSummary {
+            id: id(),
+            sku: String::from("LACEFRONT-27"),
+            code: String::from("0000001"),
+            status: Status::UPLOADED,
+            timestamps: Timestamps::default(),
+        };
+
+        assert_eq!(json!(summary), json!({
+            "id": id(),
+            "sku": "LACEFRONT-27",
+            "code": "0000001",
+            "status": "UPLOADED",
+            "timestamps": {
+                "created_at": null,
+                "uploaded_at": null,
+                "checked_at": null,
+                "finished_at": null
+            }
+        }));
+    }
+
+    #[test]
+    fn import_shapes() {
+        let status = ImportOutcome::Check(ImportCheck::Status(ImportStatus { id: id(), status: Status::UPLOADED }));
+        assert_eq!(json!(status), json!({ "id": id(), "status": "UPLOADED" }));
+
+        let result = ImportCheck::Result(ImportResult {
+            id: id(),
+            status: Status::FINISHED,
+            result: UploadResult::new(0, 1, 0, 1, Vec::new()),
+        });
+        assert_eq!(json!(result), json!({
+            "id": id(),
+            "status": "FINISHED",
+            "result": { "errors": 0, "warnings": 1, "skipped": 0, "total": 1, "result": [] }
+        }));
+
+        let error = ImportOutcome::Error(ErrorBody::with_id(id(), "Could not upload"));
+        assert_eq!(json!(error), json!({ "id": id(), "error": "Could not upload" }));
+    }
+}
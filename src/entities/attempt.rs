@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use sha2::{Digest, Sha256};
+use chrono::{DateTime, Utc};
+use crate::entities::{
+    product::Product,
+    upload_result::{Status, UploadResult},
+};
+
+/// Hex encoded SHA-256 of the product as it is sent to Kaspi
+pub fn product_hash(product: &Product) -> String {
+    let json = serde_json::to_string(product).expect("Could not create json");
+
+    hex::encode(Sha256::digest(json.as_bytes()))
+}
+
+/// One submission of a product to Kaspi
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, ToSchema)]
+pub struct Attempt {
+    /// Unknown for attempts made before the history was kept
+    pub at: Option<DateTime<Utc>>,
+    pub code: String,
+    pub product_hash: String,
+    pub status: Status,
+    pub result: Option<UploadResult>,
+}
+
+impl Attempt {
+    pub fn new(code: String, product: &Product) -> Self {
+        Self {
+            at: Some(Utc::now()),
+            code,
+            product_hash: product_hash(product),
+            status: Status::UPLOADED,
+            result: None,
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 #[allow(non_snake_case)]
 #[derive(Serialize, Deserialize, Debug)]
@@ -9,14 +10,14 @@ pub struct KaspiCategoryAttribute {
     pub mandatory: bool,
 }
 
-#[derive(Serialize, Deserialize, Eq, Hash, PartialEq, Debug, Clone)]
+#[derive(Serialize, Deserialize, Eq, Hash, PartialEq, Debug, Clone, ToSchema)]
 #[serde(untagged)]
 pub enum AttributeValue {
     String(String),
     Boolean(bool)
 }
 
-#[derive(Serialize, Deserialize, Eq, Hash, PartialEq, Debug, Clone)]
+#[derive(Serialize, Deserialize, Eq, Hash, PartialEq, Debug, Clone, ToSchema)]
 pub struct Attribute {
     pub code: String,
     pub value: AttributeValue,
@@ -2,3 +2,5 @@ pub mod category;
 pub mod attribute;
 pub mod product;
 pub mod upload_result;
+pub mod attempt;
+pub mod timestamps;
@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use crate::entities::{
+    attempt::{Attempt, product_hash},
     attribute::Attribute,
-    upload_result::{UploadStatus, UploadResult},
+    timestamps::Timestamps,
+    upload_result::{Status, UploadResult},
 };
 use std::fmt;
 use uuid::Uuid;
@@ -16,12 +19,12 @@ pub enum ProductError {
 }
 */
 
-#[derive(Serialize, Deserialize, Eq, Hash, PartialEq, Debug, Clone)]
-struct ProductImage {
+#[derive(Serialize, Deserialize, Eq, Hash, PartialEq, Debug, Clone, ToSchema)]
+pub struct ProductImage {
     url: String
 }
 
-#[derive(Serialize, Deserialize, Eq, Hash, PartialEq, Debug, Clone)]
+#[derive(Serialize, Deserialize, Eq, Hash, PartialEq, Debug, Clone, ToSchema)]
 pub struct Product {
     sku: String,
     title: String,
@@ -33,9 +36,95 @@ pub struct Product {
 }
 
 impl Product {
+    pub fn new(sku: String, title: String, brand: String, category: String, description: String, attributes: Vec<Attribute>, image_urls: Vec<String>) -> Self {
+        let images = image_urls.into_iter().map(|url| ProductImage { url }).collect();
+
+        Self { sku, title, brand, category, description, attributes, images }
+    }
+
     pub fn sku(&self) -> &String {
         &self.sku
     }
+
+    pub fn title(&self) -> &String {
+        &self.title
+    }
+
+    pub fn description(&self) -> &String {
+        &self.description
+    }
+
+    pub fn attributes(&self) -> &Vec<Attribute> {
+        &self.attributes
+    }
+
+    pub fn attributes_mut(&mut self) -> &mut Vec<Attribute> {
+        &mut self.attributes
+    }
+
+    pub fn image_urls(&self) -> Vec<&String> {
+        self.images.iter().map(|image| &image.url).collect()
+    }
+
+    /// Returns false if the product already has the image
+    pub fn add_image(&mut self, url: String) -> bool {
+        if self.images.iter().any(|image| image.url == url) {
+            return false;
+        }
+
+        self.images.push(ProductImage { url });
+        true
+    }
+
+    /// Returns false if the product has no such image
+    pub fn remove_image(&mut self, url: &str) -> bool {
+        let len = self.images.len();
+        self.images.retain(|image| image.url != url);
+
+        self.images.len() != len
+    }
+
+    /// Keeps the first of the repeated images
+    /// Returns the URLs of the dropped ones
+    pub fn dedup_images(&mut self) -> Vec<String> {
+        let mut seen = std::collections::HashSet::new();
+        let mut removed = Vec::new();
+
+        self.images.retain(|image| {
+            if seen.insert(image.url.clone()) {
+                true
+            } else {
+                removed.push(image.url.clone());
+                false
+            }
+        });
+
+        removed
+    }
+
+    pub fn brand(&self) -> &String {
+        &self.brand
+    }
+
+    pub fn category(&self) -> &String {
+        &self.category
+    }
+
+    pub fn set_title(&mut self, title: String) {
+        self.title = title;
+    }
+
+    pub fn set_brand(&mut self, brand: String) {
+        self.brand = brand;
+    }
+
+    pub fn set_category(&mut self, category: String) {
+        self.category = category;
+    }
+
+    pub fn set_description(&mut self, description: String) {
+        self.description = description;
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Eq, Hash, PartialEq, Clone)]
@@ -43,11 +132,55 @@ pub struct Record {
     id: Uuid,
     code: String,
     product: Product,
-    status: UploadStatus,
+    status: Status,
     result: Option<UploadResult>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    history: Vec<Attempt>,
+    #[serde(default, skip_serializing_if = "Timestamps::is_empty")]
+    timestamps: Timestamps,
 }
 
 impl Record {
+    pub fn new(id: Uuid, code: String, status: Status, product: Product, result: Option<UploadResult>, history: Vec<Attempt>, timestamps: Timestamps) -> Self {
+        Self { id, code, product, status, result, history, timestamps }
+    }
+
+    pub fn timestamps(&self) -> Timestamps {
+        self.timestamps
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn code(&self) -> &String {
+        &self.code
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn result(&self) -> Option<&UploadResult> {
+        self.result.as_ref()
+    }
+
+    /// Returns the attempts, oldest first
+    /// Records saved before the history was kept get one attempt from their last upload
+    pub fn history(&self) -> Vec<Attempt> {
+        if !self.history.is_empty() {
+            return self.history.clone();
+        }
+
+        vec![Attempt {
+            at: None,
+            code: self.code.clone(),
+            product_hash: product_hash(&self.product),
+            status: self.status,
+            result: self.result.clone(),
+        }]
+    }
+
     pub fn sku(&self) -> &String {
         &self.product.sku
     }
@@ -188,7 +321,8 @@ mod tests {
             "id": id,
             "product": product,
             "result": null,
-            "code": "00101001"
+            "code": "00101001",
+            "status": "UPLOADED"
         });
 
         let record: Record = serde_json::from_value(record_json.clone()).unwrap();
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use chrono::{DateTime, Duration, Utc};
+
+/// Lifecycle of a record
+/// Times are unknown for records saved before they were kept
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash, ToSchema)]
+pub struct Timestamps {
+    pub created_at: Option<DateTime<Utc>>,
+    pub uploaded_at: Option<DateTime<Utc>>,
+    pub checked_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl Timestamps {
+    pub fn created() -> Self {
+        Self {
+            created_at: Some(Utc::now()),
+            ..Self::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Time spent since the last upload, until it was archived or until `now`
+    pub fn upload_age(&self, now: DateTime<Utc>) -> Option<Duration> {
+        let uploaded_at = self.uploaded_at?;
+
+        Some(self.finished_at.unwrap_or(now) - uploaded_at)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_age_stops_when_archived() {
+        let now = Utc::now();
+        let mut timestamps = Timestamps::created();
+        assert_eq!(timestamps.upload_age(now), None);
+
+        timestamps.uploaded_at = Some(now - Duration::hours(3));
+        assert_eq!(timestamps.upload_age(now), Some(Duration::hours(3)));
+
+        timestamps.finished_at = Some(now - Duration::hours(1));
+        assert_eq!(timestamps.upload_age(now), Some(Duration::hours(2)));
+    }
+
+    #[test]
+    fn empty_timestamps() {
+        assert!(Timestamps::default().is_empty());
+        assert!(!Timestamps::created().is_empty());
+    }
+}
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug, Eq, Hash)]
+#[derive(Serialize, Deserialize, PartialEq, Copy, Clone, Debug, Eq, Hash, ToSchema)]
 pub enum Status {
     UPLOADED,
     FINISHED,
@@ -39,7 +40,7 @@ impl UploadStatus {
 }
 
 
-#[derive(Serialize, Deserialize, Eq, Hash, PartialEq, Clone, Debug)]
+#[derive(Serialize, Deserialize, Eq, Hash, PartialEq, Clone, Debug, ToSchema)]
 pub struct UploadResult {
     errors: usize,
     warnings: usize,
@@ -52,4 +53,8 @@ impl UploadResult {
     pub fn new(errors: usize, warnings: usize, skipped: usize, total: usize, result: Vec<String>) -> Self {
         Self { errors, warnings, skipped, total, result }
     }
+
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
 }
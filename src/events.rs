@@ -0,0 +1,86 @@
+use uuid::Uuid;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use crate::entities::upload_result::Status;
+
+pub const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    ProductAdded,
+    CodeAssigned,
+    Archived,
+    ResultStored,
+}
+
+/// Change of a `Store` record
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq, Eq)]
+pub struct StoreEvent {
+    pub kind: EventKind,
+    pub id: Uuid,
+    pub sku: Option<String>,
+    pub code: Option<String>,
+    pub status: Option<Status>,
+}
+
+impl StoreEvent {
+    /// Formats the event as a Server-Sent Events message
+    pub fn to_sse(&self) -> String {
+        format!(
+            "event: {}\ndata: {}\n\n",
+            serde_json::to_value(self.kind).expect("Could not create Value").as_str().unwrap(),
+            serde_json::to_string(self).expect("Could not create json")
+        )
+    }
+}
+
+#[derive(Deserialize, IntoParams, Debug, Default)]
+#[into_params(parameter_in = Query)]
+pub struct EventFilter {
+    pub sku: Option<String>,
+    pub status: Option<Status>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &StoreEvent) -> bool {
+        let sku = self.sku.as_ref().is_none_or(|sku| event.sku.as_ref() == Some(sku));
+        let status = self.status.is_none_or(|status| event.status == Some(status));
+
+        sku && status
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event() -> StoreEvent {
+        StoreEvent {
+            kind: EventKind::Archived,
+            id: Uuid::new_v5(&Uuid::NAMESPACE_URL, b"LACEFRONT-27"),
+            sku: Some(String::from("LACEFRONT-27")),
+            code: Some(String::from("0000001")),
+            status: Some(Status::FINISHED),
+        }
+    }
+
+    #[test]
+    fn filter_by_sku_and_status() {
+        let event = event();
+
+        assert!(EventFilter::default().matches(&event));
+        assert!(EventFilter { sku: Some(String::from("LACEFRONT-27")), status: Some(Status::FINISHED) }.matches(&event));
+        assert!(!EventFilter { sku: Some(String::from("LACEFRONT-28")), status: None }.matches(&event));
+        assert!(!EventFilter { sku: None, status: Some(Status::ABORTED) }.matches(&event));
+    }
+
+    #[test]
+    fn event_to_sse() {
+        let sse = event().to_sse();
+
+        assert!(sse.starts_with("event: archived\ndata: {"));
+        assert!(sse.ends_with("}\n\n"));
+    }
+}
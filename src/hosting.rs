@@ -0,0 +1,154 @@
+use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use crate::images::{check_url, MAX_BYTES, MIN_SIDE};
+
+pub const DEFAULT_DIR: &str = "images";
+/// Longest side of a hosted image after resizing
+pub const MAX_SIDE: u32 = 2000;
+
+/// Image files served to Kaspi under a public URL
+pub struct ImageHost {
+    dir: PathBuf,
+    /// Hosting is disabled without it
+    base_url: Option<String>,
+}
+
+impl ImageHost {
+    pub fn new(dir: impl Into<PathBuf>, base_url: &str) -> Self {
+        Self {
+            dir: dir.into(),
+            base_url: Some(base_url.trim_end_matches('/').to_owned()),
+        }
+    }
+
+    /// Reads IMAGES_DIR and IMAGES_BASE_URL, the public address of IMAGES_DIR
+    /// Kaspi only fetches images from public HTTPS URLs, so there is no default address
+    /// and hosting stays disabled when the address is missing or not public
+    pub fn from_env() -> Self {
+        let dir = dotenv::var("IMAGES_DIR").unwrap_or_else(|_| DEFAULT_DIR.to_owned());
+
+        match dotenv::var("IMAGES_BASE_URL") {
+            Ok(base_url) => match check_url(&base_url) {
+                Ok(()) => Self::new(dir, &base_url),
+                Err(e) => {
+                    log::warn!("IMAGES_BASE_URL '{}' can not be fetched by Kaspi: {}", base_url, e);
+                    Self { dir: dir.into(), base_url: None }
+                }
+            },
+            Err(_) => Self { dir: dir.into(), base_url: None },
+        }
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    /// Returns the public address of the images, if hosting is enabled
+    pub fn base_url(&self) -> Option<&str> {
+        self.base_url.as_deref()
+    }
+
+    /// Stores the image under the hash of its contents
+    /// Returns the public URL of the file
+    pub async fn save(&self, bytes: Vec<u8>) -> Result<String, String> {
+        let Some(base_url) = self.base_url() else {
+            return Err(String::from("Image hosting is disabled, IMAGES_BASE_URL is not set"));
+        };
+        let bytes = fit(bytes)?;
+        let extension = extension(&bytes)?;
+
+        let size = imagesize::blob_size(&bytes).map_err(|e| format!("Could not read dimensions: {}", e))?;
+        if size.width < MIN_SIDE || size.height < MIN_SIDE {
+            return Err(format!("{}x{} is smaller than {}x{}", size.width, size.height, MIN_SIDE, MIN_SIDE));
+        }
+        if bytes.len() as u64 > MAX_BYTES {
+            return Err(format!("Larger than {} bytes", MAX_BYTES));
+        }
+
+        let name = format!("{}.{}", hex::encode(Sha256::digest(&bytes)), extension);
+        let path = self.dir.join(&name);
+
+        // The same contents are already stored under the same name
+        if fs::metadata(&path).await.is_err() {
+            fs::create_dir_all(&self.dir).await.map_err(|e| e.to_string())?;
+            crate::json_processing::write_atomic(path.to_str().expect("Image path is not UTF-8"), &bytes)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        Ok(format!("{}/{}", base_url, name))
+    }
+}
+
+fn extension(bytes: &[u8]) -> Result<&'static str, String> {
+    match imagesize::image_type(bytes) {
+        Ok(imagesize::ImageType::Jpeg) => Ok("jpg"),
+        Ok(imagesize::ImageType::Png) => Ok("png"),
+        Ok(imagesize::ImageType::Webp) => Ok("webp"),
+        Ok(other) => Err(format!("Unsupported image type {:?}", other)),
+        Err(e) => Err(format!("Not an image: {}", e)),
+    }
+}
+
+/// Downscales images larger than `MAX_SIDE` or `MAX_BYTES` and re-encodes them as JPEG
+#[cfg(feature = "resize")]
+fn fit(bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+    let size = imagesize::blob_size(&bytes).map_err(|e| format!("Could not read dimensions: {}", e))?;
+    let too_large = size.width.max(size.height) > MAX_SIDE as usize || bytes.len() as u64 > MAX_BYTES;
+    if !too_large {
+        return Ok(bytes);
+    }
+
+    let image = image::load_from_memory(&bytes).map_err(|e| format!("Could not decode: {}", e))?;
+    let image = if image.width().max(image.height()) > MAX_SIDE {
+        image.resize(MAX_SIDE, MAX_SIDE, image::imageops::FilterType::Lanczos3)
+    } else {
+        image
+    };
+
+    let mut encoded = std::io::Cursor::new(Vec::new());
+    image.to_rgb8()
+        .write_to(&mut encoded, image::ImageFormat::Jpeg)
+        .map_err(|e| format!("Could not encode: {}", e))?;
+
+    Ok(encoded.into_inner())
+}
+
+#[cfg(not(feature = "resize"))]
+fn fit(bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+    Ok(bytes)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::png;
+
+    #[actix_rt::test]
+    async fn save_by_hash() {
+        let dir = std::env::temp_dir().join(format!("kaspi-service-{}", uuid::Uuid::new_v4()));
+        let host = ImageHost::new(&dir, "https://cdn.pariki.kz/images/");
+
+        let url = host.save(png(800, 600)).await.unwrap();
+        assert!(url.starts_with("https://cdn.pariki.kz/images/"));
+        assert!(url.ends_with(".png"));
+        assert_eq!(host.save(png(800, 600)).await.unwrap(), url);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        assert!(host.save(png(100, 100)).await.unwrap_err().contains("smaller"));
+        assert!(host.save(b"<html></html>".to_vec()).await.unwrap_err().contains("Not an image"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    #[actix_rt::test]
+    async fn disabled_without_base_url() {
+        let dir = std::env::temp_dir().join(format!("kaspi-service-{}", uuid::Uuid::new_v4()));
+        let host = ImageHost { dir: dir.clone(), base_url: None };
+
+        assert_eq!(host.base_url(), None);
+        assert!(host.save(png(800, 600)).await.unwrap_err().contains("disabled"));
+        assert!(!dir.exists());
+    }
+}
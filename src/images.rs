@@ -0,0 +1,223 @@
+use std::time::Duration;
+use reqwest::{Client, Url, header};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::entities::product::Product;
+
+pub const ALLOWED_TYPES: [&str; 3] = ["image/jpeg", "image/png", "image/webp"];
+pub const MAX_BYTES: u64 = 10 * 1024 * 1024;
+pub const MIN_SIDE: usize = 500;
+pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 5;
+pub const DEFAULT_TIMEOUT_SECS: u64 = 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct ImageProblem {
+    pub url: String,
+    pub problem: String,
+}
+
+/// Problems found in the images of one product
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct ImageReport {
+    pub sku: String,
+    /// Repeated URLs, already dropped from the product
+    pub removed_duplicates: Vec<String>,
+    pub problems: Vec<ImageProblem>,
+}
+
+impl ImageReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.is_ok() && self.removed_duplicates.is_empty()
+    }
+
+    fn problem(&mut self, url: &str, problem: impl Into<String>) {
+        self.problems.push(ImageProblem { url: url.to_owned(), problem: problem.into() });
+    }
+}
+
+/// Drops repeated image URLs and checks the rest can be fetched by Kaspi
+pub fn check_urls(product: &mut Product) -> ImageReport {
+    let mut report = ImageReport {
+        sku: product.sku().to_owned(),
+        removed_duplicates: product.dedup_images(),
+        ..ImageReport::default()
+    };
+
+    for url in product.image_urls().iter() {
+        if let Err(problem) = check_url(url) {
+            report.problem(url, problem);
+        }
+    }
+
+    report
+}
+
+/// Checks Kaspi can fetch the URL
+pub fn check_url(url: &str) -> Result<(), String> {
+    match Url::parse(url) {
+        Ok(parsed) if parsed.scheme() != "https" => Err(String::from("Only HTTPS URLs are accepted")),
+        Ok(parsed) if parsed.host_str().is_none_or(|h| !h.contains('.')) => Err(String::from("URL has no public host")),
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Malformed URL: {}", e)),
+    }
+}
+
+/// Downloads the images to check their type, size and dimensions
+pub struct ImageProber {
+    client: Client,
+    max_bytes: u64,
+    min_side: usize,
+}
+
+impl ImageProber {
+    pub fn new(client: Client) -> Self {
+        Self { client, max_bytes: MAX_BYTES, min_side: MIN_SIDE }
+    }
+
+    /// Reads IMAGE_PROBE_CONNECT_TIMEOUT and IMAGE_PROBE_TIMEOUT, in seconds
+    /// A slow image host must not hold the request that probes it
+    pub fn from_env() -> Self {
+        let seconds = |key: &str, default: u64| {
+            dotenv::var(key).ok().and_then(|v| v.parse::<u64>().ok()).unwrap_or(default)
+        };
+
+        let client = Client::builder()
+            .connect_timeout(Duration::from_secs(seconds("IMAGE_PROBE_CONNECT_TIMEOUT", DEFAULT_CONNECT_TIMEOUT_SECS)))
+            .timeout(Duration::from_secs(seconds("IMAGE_PROBE_TIMEOUT", DEFAULT_TIMEOUT_SECS)))
+            .build()
+            .expect("Could not build the image client");
+
+        Self::new(client)
+    }
+
+    pub fn with_limits(client: Client, max_bytes: u64, min_side: usize) -> Self {
+        Self { client, max_bytes, min_side }
+    }
+
+    pub async fn probe(&self, url: &str) -> Result<(), String> {
+        let response = self.client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| format!("Could not fetch: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(format!("Responded with {}", response.status()));
+        }
+
+        let content_type = response.headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.split(';').next().unwrap_or_default().trim().to_lowercase())
+            .unwrap_or_default();
+        if !ALLOWED_TYPES.contains(&content_type.as_str()) {
+            return Err(format!("Unsupported content type '{}'", content_type));
+        }
+
+        if response.content_length().is_some_and(|len| len > self.max_bytes) {
+            return Err(format!("Larger than {} bytes", self.max_bytes));
+        }
+
+        let bytes = response.bytes()
+            .await
+            .map_err(|e| format!("Could not download: {}", e))?;
+        if bytes.len() as u64 > self.max_bytes {
+            return Err(format!("Larger than {} bytes", self.max_bytes));
+        }
+
+        let size = imagesize::blob_size(&bytes)
+            .map_err(|e| format!("Could not read dimensions: {}", e))?;
+        if size.width < self.min_side || size.height < self.min_side {
+            return Err(format!(
+                "{}x{} is smaller than {}x{}", size.width, size.height, self.min_side, self.min_side
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Probes every well-formed URL of the product, adding the problems to the report
+    pub async fn check(&self, product: &Product, report: &mut ImageReport) {
+        for url in product.image_urls().iter() {
+            if report.problems.iter().any(|p| &p.url == *url) {
+                continue;
+            }
+
+            if let Err(problem) = self.probe(url).await {
+                report.problem(url, problem);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, HttpResponse};
+    use crate::testing::{png, product_json, serve};
+
+    fn product(urls: &[&str]) -> Product {
+        let images: Vec<serde_json::Value> = urls.iter().map(|url| serde_json::json!({ "url": url })).collect();
+
+        let mut product = product_json("LACEFRONT-27", "Title");
+        product["images"] = serde_json::json!(images);
+
+        serde_json::from_value(product).unwrap()
+    }
+
+    #[test]
+    fn duplicates_and_schemes() {
+        let url = "https://old.pariki.kz/wp-content/uploads/2022/11/photo_5452090876806414405_y.jpg";
+        let mut product = product(&[url, url, "http://pariki.kz/a.jpg", "not a url", "https://localhost/a.jpg"]);
+
+        let report = check_urls(&mut product);
+
+        assert_eq!(report.removed_duplicates, vec![url.to_string()]);
+        assert_eq!(product.image_urls().len(), 4);
+        let bad: Vec<&str> = report.problems.iter().map(|p| p.url.as_str()).collect();
+        assert_eq!(bad, vec!["http://pariki.kz/a.jpg", "not a url", "https://localhost/a.jpg"]);
+        // Images may be added after the product is created
+        assert!(check_urls(&mut self::product(&[])).is_clean());
+    }
+
+    #[actix_rt::test]
+    async fn probe_local_server() {
+        let (base_url, server) = serve(|config| {
+            config
+                .route("/big.png", web::get().to(|| async {
+                    HttpResponse::Ok().content_type("image/png").body(png(800, 600))
+                }))
+                .route("/small.png", web::get().to(|| async {
+                    HttpResponse::Ok().content_type("image/png").body(png(100, 100))
+                }))
+                .route("/page", web::get().to(|| async {
+                    HttpResponse::Ok().content_type("text/html").body("<html></html>")
+                }))
+                .route("/slow.png", web::get().to(|| async {
+                    actix_rt::time::sleep(Duration::from_secs(2)).await;
+                    HttpResponse::Ok().content_type("image/png").body(png(800, 600))
+                }));
+        });
+
+        let prober = ImageProber::new(Client::new());
+        let url = |path: &str| format!("{}{}", base_url, path);
+
+        assert_eq!(prober.probe(&url("/big.png")).await, Ok(()));
+        assert!(prober.probe(&url("/small.png")).await.unwrap_err().contains("smaller"));
+        assert!(prober.probe(&url("/page")).await.unwrap_err().contains("content type"));
+        assert!(prober.probe(&url("/missing")).await.unwrap_err().contains("404"));
+
+        let limited = ImageProber::with_limits(Client::new(), 10, 1);
+        assert!(limited.probe(&url("/big.png")).await.unwrap_err().contains("Larger"));
+
+        let impatient = ImageProber::new(Client::builder().timeout(Duration::from_millis(100)).build().unwrap());
+        assert!(impatient.probe(&url("/slow.png")).await.unwrap_err().contains("Could not fetch"));
+
+        server.stop(false).await;
+    }
+}
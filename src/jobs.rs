@@ -0,0 +1,223 @@
+use uuid::Uuid;
+use std::{
+    sync::{Arc, atomic::{AtomicBool, AtomicUsize, Ordering}},
+    collections::HashMap,
+    time::Duration,
+};
+use reqwest::Client;
+use serde::Serialize;
+use utoipa::ToSchema;
+use tokio::sync::{mpsc, Mutex};
+use crate::{send_to_kaspi, entities::product::Product};
+
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+pub enum JobState {
+    QUEUED,
+    RUNNING,
+    DONE,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct Job {
+    id: Uuid,
+    state: JobState,
+    total: usize,
+    queued: usize,
+    sent: usize,
+    failed: usize,
+    codes: Vec<String>,
+    errors: Vec<String>,
+}
+
+impl Job {
+    fn new(id: Uuid, total: usize) -> Self {
+        Self {
+            id,
+            state: if total == 0 { JobState::DONE } else { JobState::QUEUED },
+            total,
+            queued: total,
+            sent: 0,
+            failed: 0,
+            codes: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn state(&self) -> JobState {
+        self.state
+    }
+
+    fn take(&mut self) {
+        self.queued -= 1;
+        self.state = JobState::RUNNING;
+    }
+
+    fn finish(&mut self, result: Result<String, String>) {
+        match result {
+            Ok(code) => {
+                self.sent += 1;
+                self.codes.push(code);
+            }
+            Err(e) => {
+                self.failed += 1;
+                self.errors.push(e);
+            }
+        }
+
+        if self.sent + self.failed == self.total {
+            self.state = JobState::DONE;
+        }
+    }
+}
+
+struct Task {
+    job: Uuid,
+    product: Product,
+}
+
+/// Queue of upload jobs drained by a fixed number of workers
+pub struct JobQueue {
+    jobs: Mutex<HashMap<Uuid, Job>>,
+    sender: mpsc::UnboundedSender<Task>,
+    receiver: Mutex<mpsc::UnboundedReceiver<Task>>,
+    closed: AtomicBool,
+    in_flight: AtomicUsize,
+}
+
+impl Default for JobQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JobQueue {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        Self {
+            jobs: Mutex::new(HashMap::new()),
+            sender,
+            receiver: Mutex::new(receiver),
+            closed: AtomicBool::new(false),
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    /// Spawns `concurrency` workers, each uploading one product at a time
+    pub fn start(&'static self, concurrency: usize, client: Arc<Client>) {
+        for _ in 0..concurrency.max(1) {
+            let client = client.clone();
+
+            actix_rt::spawn(async move {
+                while let Some(task) = self.next().await {
+                    let result = send_to_kaspi(
+                        serde_json::to_value(task.product).expect("Could not convert to json"),
+                        client.clone()
+                    ).await;
+
+                    if let Some(job) = self.jobs.lock().await.get_mut(&task.job) {
+                        job.finish(result);
+                    }
+                    self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                }
+            });
+        }
+    }
+
+    async fn next(&self) -> Option<Task> {
+        let mut receiver = self.receiver.lock().await;
+        if self.closed.load(Ordering::SeqCst) {
+            return None;
+        }
+
+        let task = receiver.recv().await?;
+        if self.closed.load(Ordering::SeqCst) {
+            receiver.close();
+            return None;
+        }
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        drop(receiver);
+
+        if let Some(job) = self.jobs.lock().await.get_mut(&task.job) {
+            job.take();
+        }
+
+        Some(task)
+    }
+
+    /// Creates a job for the products and returns its id
+    pub async fn enqueue(&self, products: Vec<Product>) -> Uuid {
+        let id = Uuid::new_v4();
+
+        self.jobs.lock().await.insert(id, Job::new(id, products.len()));
+
+        for product in products.into_iter() {
+            self.sender
+                .send(Task { job: id, product })
+                .expect("Job queue is closed");
+        }
+
+        id
+    }
+
+    /// Stops the workers from taking new products and waits for the uploads in flight
+    /// Returns the number of products left in the queue
+    pub async fn shutdown(&self) -> usize {
+        self.closed.store(true, Ordering::SeqCst);
+
+        while self.in_flight.load(Ordering::SeqCst) > 0 {
+            actix_rt::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        self.queued_len().await
+    }
+
+    /// Returns the job
+    /// Otherwise, _None_
+    pub async fn get(&self, id: &Uuid) -> Option<Job> {
+        self.jobs.lock().await.get(id).cloned()
+    }
+
+    /// Returns the number of products waiting for a worker
+    pub async fn queued_len(&self) -> usize {
+        self.jobs.lock().await.values().map(|job| job.queued).sum()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::product;
+
+    #[actix_rt::test]
+    async fn enqueue_and_count() {
+        let queue = JobQueue::new();
+
+        let empty = queue.enqueue(Vec::new()).await;
+        assert_eq!(queue.get(&empty).await.unwrap().state(), JobState::DONE);
+
+        let product = product("LACEFRONT-27", "Title");
+
+        let id = queue.enqueue(vec![product.clone(), product]).await;
+        assert_eq!(queue.get(&id).await.unwrap().state(), JobState::QUEUED);
+        assert_eq!(queue.queued_len().await, 2);
+
+        queue.next().await.unwrap();
+        let mut job = queue.get(&id).await.unwrap();
+        assert_eq!(job.state(), JobState::RUNNING);
+        assert_eq!(queue.queued_len().await, 1);
+
+        job.take();
+        job.finish(Ok(String::from("0000001")));
+        job.finish(Err(String::from("Duplicate product LACEFRONT-27")));
+        assert_eq!(job.state(), JobState::DONE);
+        assert_eq!((job.sent, job.failed), (1, 1));
+    }
+}
@@ -1,7 +1,28 @@
 use crate::entities::product::Record;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use tokio::{fs, io::{self, AsyncReadExt, AsyncWriteExt}};
 
-pub const FILE_NAME: &'static str = "products.json";
+pub const FILE_NAME: &str = "products.json";
+
+/// Version of the data file layout written by this build
+/// 0 - a flat array of records
+/// 1 - `DataFile` with the records under a version header
+pub const SCHEMA_VERSION: u64 = 1;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct DataFile {
+    pub version: u64,
+    pub records: Vec<Value>,
+}
+
+/// Records read from the data file
+/// Entries that could not be parsed are kept with the reason
+#[derive(Debug, Default)]
+pub struct Loaded {
+    pub records: Vec<Record>,
+    pub rejected: Vec<(Value, String)>,
+}
 
 pub async fn open_file(file_name: &str) -> io::Result<fs::File> {
     let res = fs::File::open(file_name).await;
@@ -12,7 +33,8 @@ pub async fn open_file(file_name: &str) -> io::Result<fs::File> {
             let mut f = fs::File::create(file_name).await.expect("Could not create a json file");
             f.write_all(b"[]").await.expect("Could not populate json file");
 
-            Ok(f)
+            // The created file is write-only
+            fs::File::open(file_name).await
         }
     }
 }
@@ -30,32 +52,179 @@ pub async fn read_json(file_name: &str) -> serde_json::Result<Vec<serde_json::Va
     Ok(a)
 }
 
+/// Brings the contents of a data file of any known version to `SCHEMA_VERSION`
+pub fn migrate(json: Value) -> anyhow::Result<DataFile> {
+    match json {
+        // Version 0 had no header
+        Value::Array(records) => Ok(DataFile { version: SCHEMA_VERSION, records }),
+        Value::Object(_) => {
+            let file: DataFile = serde_json::from_value(json)?;
+
+            match file.version {
+                SCHEMA_VERSION => Ok(file),
+                v if v > SCHEMA_VERSION => anyhow::bail!("Data file version {} is newer than {}", v, SCHEMA_VERSION),
+                v => anyhow::bail!("Unknown data file version {}", v),
+            }
+        }
+        _ => anyhow::bail!("Data file is neither an array nor a versioned object"),
+    }
+}
+
+pub async fn read_records(file_name: &str) -> anyhow::Result<Loaded> {
+    let file = open_file(file_name).await?;
+
+    let mut buf_reader = io::BufReader::new(file);
+    let mut contents = String::new();
+    buf_reader.read_to_string(&mut contents).await?;
+
+    let file = migrate(serde_json::from_str(contents.as_str())?)?;
+
+    let mut loaded = Loaded::default();
+    for entry in file.records.into_iter() {
+        match serde_json::from_value::<Record>(entry.clone()) {
+            Ok(record) => loaded.records.push(record),
+            Err(e) => loaded.rejected.push((entry, e.to_string())),
+        }
+    }
+
+    Ok(loaded)
+}
+
+pub async fn write_records(file_name: &str, records: &[Record]) -> io::Result<()> {
+    let file = DataFile {
+        version: SCHEMA_VERSION,
+        records: records.iter()
+            .map(|r| serde_json::to_value(r).expect("Could not create Value"))
+            .collect(),
+    };
+
+    save_json(file_name, serde_json::to_value(file).expect("Could not create Value")).await
+}
+
+pub fn quarantine_file_name(file_name: &str) -> String {
+    format!("{}.quarantine", file_name)
+}
+
+/// Moves the rejected entries aside, so they are not lost on the next save
+pub async fn quarantine(file_name: &str, rejected: Vec<(Value, String)>) -> io::Result<()> {
+    if rejected.is_empty() {
+        return Ok(());
+    }
+
+    let quarantine_name = quarantine_file_name(file_name);
+    let mut entries = read_json(quarantine_name.as_str()).await.unwrap_or_default();
+
+    for (entry, error) in rejected.into_iter() {
+        log::warn!("Quarantined an entry of {}: {}", file_name, error);
+        entries.push(serde_json::json!({ "error": error, "entry": entry }));
+    }
+
+    save_json(quarantine_name.as_str(), Value::Array(entries)).await
+}
+
+/// Writes to a temporary file first, so a crash cannot leave a half written file behind
+pub async fn write_atomic(file_name: &str, bytes: &[u8]) -> io::Result<()> {
+    let temp_name = format!("{}.{}.tmp", file_name, uuid::Uuid::new_v4());
+    let mut file = fs::File::create(&temp_name).await?;
+
+    file.write_all(bytes).await?;
+    file.sync_all().await?;
+    drop(file);
+
+    fs::rename(&temp_name, file_name).await.inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp_name);
+    })
+}
+
 pub async fn save_json(file_name: &str, json: serde_json::Value) -> io::Result<()> {
-    let mut file = fs::File::create(file_name).await.expect("Could not create a file");
+    write_atomic(file_name, json.to_string().as_bytes()).await
+}
+
+/// Applies `func` to the records in the data file and writes them back
+pub async fn modify_record(file_name: &str, mut func: impl FnMut(Vec<Record>) -> anyhow::Result<Vec<Record>>) -> anyhow::Result<()> {
+    let loaded = read_records(file_name).await?;
+    quarantine(file_name, loaded.rejected).await?;
+
+    let records = func(loaded.records)?;
+    write_records(file_name, &records).await?;
 
-    file.write_all(json.to_string().as_bytes()).await?;
     Ok(())
 }
 
-pub async fn modify_record(mut func: impl FnMut(Vec<Record>) -> anyhow::Result<Vec<Record>>) -> anyhow::Result<()> {
-    let mut file = open_file(FILE_NAME).await.expect("Could not read a json file");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::product_json;
 
-    let mut buf_reader = io::BufReader::new(&mut file);
-    let mut contents = String::new();
+    #[actix_rt::test]
+    async fn save_replaces_file() {
+        let dir = std::env::temp_dir().join(format!("kaspi-service-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let file_name = dir.join("products.json");
+        let file_name = file_name.to_str().unwrap();
 
-    buf_reader.read_to_string(&mut contents).await.expect("Could not process a json file");
+        save_json(file_name, serde_json::json!([{ "a": "b" }])).await.unwrap();
+        save_json(file_name, serde_json::json!([])).await.unwrap();
 
-    let vec = serde_json::to_value(contents.as_str()).expect("Could not create json");
-    let mut records: Vec<Record> = serde_json::from_value(vec)?;
+        assert_eq!(read_json(file_name).await.unwrap(), Vec::<serde_json::Value>::new());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
 
-    records = func(records)?;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn record() -> Record {
+        serde_json::from_value(serde_json::json!({
+            "id": "a8b1a5b4-5e2b-5b9c-9a33-2a4a6f0c2b11",
+            "code": "00101001",
+            "status": "FINISHED",
+            "product": product_json("LACEFRONT-27", "Title"),
+            "result": {
+                "errors": 0,
+                "warnings": 0,
+                "skipped": 0,
+                "total": 1,
+                "result": []
+            }
+        })).unwrap()
+    }
 
-    file.write_all(
-        serde_json::to_value(records)
-        .expect("Could not create json")
-        .to_string()
-        .as_bytes()
-    ).await?;
+    #[test]
+    fn migrate_versions() {
+        let flat = migrate(serde_json::json!([{ "a": "b" }])).unwrap();
+        assert_eq!(flat, DataFile { version: SCHEMA_VERSION, records: vec![serde_json::json!({ "a": "b" })] });
 
-    Ok(())
+        let current = migrate(serde_json::json!({ "version": SCHEMA_VERSION, "records": [] })).unwrap();
+        assert!(current.records.is_empty());
+
+        assert!(migrate(serde_json::json!({ "version": SCHEMA_VERSION + 1, "records": [] })).is_err());
+        assert!(migrate(serde_json::json!("records")).is_err());
+    }
+
+    #[actix_rt::test]
+    async fn records_round_trip() {
+        let dir = std::env::temp_dir().join(format!("kaspi-service-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let file_name = dir.join("products.json");
+        let file_name = file_name.to_str().unwrap();
+
+        // A version 0 file with one broken entry
+        let record = record();
+        save_json(file_name, serde_json::json!([record, { "id": "broken" }])).await.unwrap();
+
+        let loaded = read_records(file_name).await.unwrap();
+        assert_eq!(loaded.records, vec![record.clone()]);
+        assert_eq!(loaded.rejected.len(), 1);
+
+        modify_record(file_name, |mut records| {
+            records.push(records[0].clone());
+            Ok(records)
+        }).await.unwrap();
+
+        let loaded = read_records(file_name).await.unwrap();
+        assert_eq!(loaded.records, vec![record.clone(), record]);
+        assert!(loaded.rejected.is_empty());
+        assert_eq!(read_json(quarantine_file_name(file_name).as_str()).await.unwrap().len(), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -2,24 +2,73 @@ pub mod routes;
 pub mod json_processing;
 pub mod entities;
 pub mod store;
+pub mod jobs;
+pub mod retry;
+pub mod events;
+pub mod webhooks;
+pub mod metrics;
+pub mod query;
+pub mod search;
+pub mod images;
+pub mod hosting;
+pub mod dto;
+pub mod reconcile;
+pub mod diff;
+pub mod dictionary;
+pub mod translit;
+pub mod templates;
+pub mod bulk;
+pub mod sanitize;
+pub mod offers;
+pub mod repricing;
+#[cfg(test)]
+pub(crate) mod testing;
 
 use uuid::Uuid;
 use std::sync::Arc;
 use reqwest::Client;
-use serde_json::json;
 use lazy_static::lazy_static;
 use crate::{
     store::Store,
-    entities::{upload_result::*, product::{Product, Record}}
+    jobs::JobQueue,
+    retry::{RetryPolicy, RateLimiter, RetryStats},
+    webhooks::Webhooks,
+    metrics::Metrics,
+    images::ImageProber,
+    hosting::ImageHost,
+    reconcile::Reconciler,
+    diff::ProductDiff,
+    dictionary::Dictionaries,
+    templates::Templates,
+    offers::{Offers, Merchant},
+    repricing::Repricer,
+    entities::{upload_result::*, product::{Product, Record}},
+    dto::{ImportCheck, ImportStatus, ImportResult},
 };
 
-use anyhow::{Result, Error};
+use anyhow::Result;
 
 lazy_static!{
     pub static ref STORE: Store = Store::new();
+    pub static ref JOBS: JobQueue = JobQueue::new();
+    pub static ref RETRY_POLICY: RetryPolicy = RetryPolicy::from_env();
+    pub static ref RATE_LIMITER: RateLimiter = RateLimiter::from_env();
+    pub static ref RETRY_STATS: RetryStats = RetryStats::default();
+    pub static ref WEBHOOKS: Webhooks = Webhooks::new();
+    pub static ref METRICS: Metrics = Metrics::new();
+    pub static ref IMAGE_PROBER: ImageProber = ImageProber::from_env();
+    pub static ref IMAGE_HOST: ImageHost = ImageHost::from_env();
+    pub static ref RECONCILER: Reconciler = Reconciler::from_env();
+    pub static ref DICTIONARIES: Dictionaries = Dictionaries::from_env();
+    pub static ref TEMPLATES: Templates = Templates::new();
+    pub static ref OFFERS: Offers = Offers::default();
+    pub static ref REPRICER: Repricer = Repricer::from_env();
+    /// DRY_RUN answers every upload as a dry run
+    pub static ref DRY_RUN: bool = dotenv::var("DRY_RUN").is_ok_and(|v| v == "true" || v == "1");
 }
 
-pub(crate) async fn send_to_kaspi(product: serde_json::Value, client: Arc<Client>) -> Result<String, String> {
+/// Returns the id of the product, its JSON and the body Kaspi receives for it
+pub(crate) fn import_body(product: serde_json::Value) -> (Uuid, serde_json::Value, String) {
     // Kaspi requires an array of products
     // Create a vector for a product
     let product_vec = serde_json::to_value([product]).expect("Could not create Value");
@@ -38,137 +87,219 @@ pub(crate) async fn send_to_kaspi(product: serde_json::Value, client: Arc<Client
         .as_bytes()
     );
 
-    if let Some(product) = STORE.insert_product(id, serde_json::from_value::<Product>(product_json).unwrap()).await {
-        return Err(format!("Duplicate product {}", product.sku()));
+    (id, product_json, product_vec.to_string())
+}
+
+/// Returns the version of the product on Kaspi, if uploading this one would not change it
+/// Only the newest version counts, older ones have been replaced on Kaspi
+pub(crate) async fn unchanged_version(id: &Uuid, product: &Product) -> Option<Uuid> {
+    let (version, stored) = STORE.latest_on_kaspi(product.sku()).await?;
+
+    if version != *id && ProductDiff::between(&stored, product).is_empty() {
+        Some(version)
+    } else {
+        None
+    }
+}
+
+pub(crate) async fn send_to_kaspi(product: serde_json::Value, client: Arc<Client>) -> Result<String, String> {
+    let (id, product_json, body) = import_body(product);
+
+    let product = serde_json::from_value::<Product>(product_json).unwrap();
+
+    // Aborted imports may be submitted again, and so may older versions Kaspi no longer holds
+    let retry = match STORE.get_status(&id).await {
+        Some((_, Status::ABORTED)) => true,
+        Some((_, Status::FINISHED)) => STORE.latest_on_kaspi(product.sku())
+            .await
+            .is_some_and(|(version, _)| version != id),
+        _ => false,
+    };
+    if let Some(version) = unchanged_version(&id, &product).await {
+        return Err(format!("Unchanged product {}, same as {}", product.sku(), version));
+    }
+
+    if let (Some(product), false) = (STORE.insert_product(id, product).await, retry) {
+        Err(format!("Duplicate product {}", product.sku()))
     } else {
         // Take response for uploading request
-        let response = client
-            .post("https://kaspi.kz/shop/api/products/import")
-            .header("Content-Type", "text/plain")
-            .body(product_vec.to_string())
-            .send()
-            .await.expect("Could not upload to kaspi");
+        let response = retry::send("import", || {
+            client
+                .post("https://kaspi.kz/shop/api/products/import")
+                .header("Content-Type", "text/plain")
+                .body(body.clone())
+        }, false).await;
+
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                // Let the product be submitted again
+                if !retry {
+                    STORE.remove_product(&id).await;
+                }
+                return Err(format!("Could not upload to kaspi: {}", e));
+            }
+        };
 
-        // Convert response to json value
-        let response = serde_json::to_value(
-            response.json::<UploadStatus>()
-            .await.expect("Could not convert json to UploadStatus")
-        ).expect("Could not create Value");
+        let code_string = match response.json::<UploadStatus>().await {
+            Ok(status) => status.get_code(),
+            Err(e) => {
+                if !retry {
+                    STORE.remove_product(&id).await;
+                }
+                return Err(format!("Could not convert json to UploadStatus: {}", e));
+            }
+        };
 
-        let code_string = response["code"].as_str().unwrap().to_string();
+        if retry {
+            STORE.reopen(&id).await;
+        }
 
         if let Some(code) = STORE.insert_upload(id, code_string.clone()).await {
-            return Err(format!("Duplicate upload {}", code));
+            Err(format!("Duplicate upload {}", code))
         } else {
             // Log the upload
-            log::info!("{}", format!("Uploaded: {:?}", id));
+            log::info!("Uploaded: {:?}", id);
             Ok(code_string)
         }
     }
 }
 
 // TODO: split in different functions for uploaded, finished products
-pub(crate) async fn check_code(id: &Uuid, client: Arc<Client>) -> Result<serde_json::Value, String> {
+pub(crate) async fn check_code(id: &Uuid, client: Arc<Client>) -> Result<ImportCheck, String> {
     if let Some((code, status)) = STORE.get_status(id).await {
         if status == Status::UPLOADED {
             check_status(id, code, status, client).await
         } else {
-            let value = json!({
-                "id": id,
-                "status": status,
-            });
-
-            Ok(value)
+            Ok(ImportCheck::Status(ImportStatus { id: *id, status }))
         }
     } else {
         Err(format!("ID: '{}' is not found", id))
     }
 }
 
-pub(crate) async fn check_status(id: &Uuid, code: String, status: Status, client: Arc<Client>) -> Result<serde_json::Value, String> {
+pub(crate) async fn check_status(id: &Uuid, code: String, status: Status, client: Arc<Client>) -> Result<ImportCheck, String> {
     // Get reponse of checking request
-    let response = client.get(
+    let response = retry::send("import_status", || client.get(
         format!("https://kaspi.kz/shop/api/products/import?i={}", code)
-    )
-    .send()
-    .await.expect("Could not get result response");
+    ), true).await?;
+
+    STORE.touch_checked(id).await;
 
     // Convert response to json value
-    let upload_status = response.json::<UploadStatus>().await.expect("Could not parse json as UploadStatus");
+    let upload_status = response.json::<UploadStatus>()
+        .await
+        .map_err(|e| format!("Could not parse json as UploadStatus: {}", e))?;
 
     match upload_status.get_status() {
-        Status::FINISHED => {
-            STORE.archive(id, Status::FINISHED).await;
-            check_result(id, code, Status::FINISHED, client).await
-        }
-        Status::ABORTED => {
-            STORE.archive(id, Status::ABORTED).await;
-            check_result(id, code, Status::ABORTED, client).await
-        }
-        _ => {
-            let value = json!({
-                "id": id,
-                "status": status,
-            });
+        archived @ (Status::FINISHED | Status::ABORTED) => {
+            // Stays uploaded until the result is stored, so it is checked again
+            let value = check_result(id, code, archived, client).await?;
+
+            // Concurrent checks may both see the import done, only the one archiving it notifies
+            if STORE.archive(id, archived).await.is_some() {
+                let id = id.to_owned();
+                actix_rt::spawn(async move { WEBHOOKS.notify(&id).await });
+            }
 
             Ok(value)
         }
+        _ => Ok(ImportCheck::Status(ImportStatus { id: *id, status })),
     }
 }
 
-pub(crate) async fn check_result(id: &Uuid, code: String, status: Status, client: Arc<Client>) -> Result<serde_json::Value, String> {
+pub(crate) async fn check_result(id: &Uuid, code: String, status: Status, client: Arc<Client>) -> Result<ImportCheck, String> {
     // Get response for result request
-    let response = client.get(
+    let response = retry::send("import_result", || client.get(
         format!("https://kaspi.kz/shop/api/products/import/result?i={}", code)
-    )
-    .send()
-    .await.expect("Could not get result response");
-
+    ), true).await?;
 
     let result = response.json::<UploadResult>()
-        .await.expect("Could not parse json as UploadResult");
+        .await
+        .map_err(|e| format!("Could not parse json as UploadResult: {}", e))?;
 
     STORE.insert_result(id.to_owned(), result.clone()).await;
 
-    // Convert response to json
-    let response_json = serde_json::to_value(result).expect("Could not create Value");
+    Ok(ImportCheck::Result(ImportResult { id: *id, status, result }))
+}
 
-    let value = json!({
-        "id": id,
-        "status": status,
-        "result": response_json
-    });
+/// Writes every record with a code to the data file
+pub async fn save() {
+    use crate::json_processing::{write_records, FILE_NAME};
+
+    log::info!("Saving...");
+
+    let mut records: Vec<Record> = Vec::new();
+
+    for (id, product, code, status) in STORE.uploaded_products().await {
+        let result = STORE.get_result(&id).await;
+
+        let history = STORE.get_history(&id).await;
+        let timestamps = STORE.get_timestamps(&id).await.unwrap_or_default();
 
-    Ok(value)
+        records.push(Record::new(id, code, status, product, result, history, timestamps));
+    }
+
+    match write_records(FILE_NAME, &records).await {
+        Ok(()) => log::info!("Saved!"),
+        Err(e) => log::error!("Could not save json file: {}", e),
+    }
 }
 
 pub async fn spawn_save() {
-    use serde_json::Value;
-    use crate::json_processing::{save_json, FILE_NAME};
+    actix_rt::spawn(save()).await.expect("Could not save record");
+}
+
+/// Reprices the offers on an interval
+/// Fails without KASPI_MERCHANT_ID, which tells own offers from competitors
+pub fn spawn_repricing(interval: std::time::Duration) -> Result<(), String> {
+    let merchant_id = Merchant::from_env().merchant_id;
+    if merchant_id.trim().is_empty() {
+        return Err(String::from("KASPI_MERCHANT_ID is required for repricing"));
+    }
 
     actix_rt::spawn(async move {
-        log::info!("Saving...");
+        let mut interval = actix_rt::time::interval(interval);
 
-        let mut records: Vec<Value> = Vec::new();
+        loop {
+            interval.tick().await;
+            match REPRICER.run(&OFFERS, &merchant_id).await {
+                Ok(outcomes) => log::info!("Repriced {} offers", outcomes.iter().filter(|o| o.changed).count()),
+                Err(e) => log::error!("Could not reprice: {}", e),
+            }
+        }
+    });
 
-        let products = STORE.products().await;
-        for (id, product) in products.iter() {
-            let (code, status) = STORE.get_status(id).await.unwrap();
-            let result = STORE.get_result(id).await;
+    Ok(())
+}
 
-            let record = json!({
-                "id": id,
-                "code": code,
-                "status": status,
-                "product": product,
-                "result": result
-            });
+/// Saves the store on an interval
+pub fn spawn_autosave(interval: std::time::Duration) {
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(interval);
+        // The first tick completes immediately
+        interval.tick().await;
 
-            records.push(record);
+        loop {
+            interval.tick().await;
+            save().await;
         }
-        let json = serde_json::to_value(records).expect("Could not create Value");
-        save_json(FILE_NAME, json).await.expect("Could not save json file");
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::product_json;
 
-        log::info!("Saved!");
-    }).await.expect("Could not save record");
+    #[test]
+    fn import_body_of_product() {
+        let product = product_json("LACEFRONT-27", "Title");
+
+        let (id, json, body) = import_body(product.clone());
+        assert_eq!(json, product);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap(), serde_json::json!([product]));
+        assert_eq!(import_body(product).0, id);
+    }
 }
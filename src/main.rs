@@ -1,3 +1,4 @@
+use actix_files::Files;
 use actix_web::{
     App, HttpServer,
     web::{self, ServiceConfig},
@@ -5,14 +6,35 @@ use actix_web::{
 };
 use reqwest::{header::{HeaderMap, HeaderValue}, Client};
 use log::info;
+use std::{sync::Arc, time::Duration};
 
 use kaspi_service::{
     spawn_save,
+    spawn_autosave,
+    spawn_repricing,
     routes::{
-        products::{show_all, show, add, remove},
+        json_error, query_error,
+        products::{show_all, search, show, history, diff, diff_candidate, add, bulk_edit, upload_images, remove},
         code::{check_all, check},
+        jobs,
+        events,
+        metrics,
+        docs,
+        reconcile,
+        attributes,
+        templates,
+        offers,
+        repricing,
     },
+    jobs::DEFAULT_CONCURRENCY,
     STORE,
+    JOBS,
+    WEBHOOKS,
+    IMAGE_HOST,
+    RECONCILER,
+    TEMPLATES,
+    OFFERS,
+    REPRICER,
 };
 
 
@@ -21,15 +43,67 @@ pub fn init(config: &mut ServiceConfig) {
         .service(
             web::scope("/products")
                 .service(show_all)
+                // Before `show`, which would take "search" for an id
+                .service(search)
                 .service(show)
+                .service(history)
+                .service(diff)
+                .service(diff_candidate)
                 .service(add)
+                .service(bulk_edit)
+                .service(upload_images)
                 .service(remove)
         )
         .service(
             web::scope("/code")
                 .service(check_all)
                 .service(check)
-        );
+        )
+        .service(
+            web::scope("/jobs")
+                .service(jobs::show)
+        )
+        .service(
+            web::scope("/reconcile")
+                .service(reconcile::start)
+                .service(reconcile::report)
+        )
+        .service(
+            web::scope("/attributes")
+                .service(attributes::values)
+        )
+        .service(
+            web::scope("/templates")
+                .service(templates::show_all)
+                .service(templates::save)
+                .service(templates::remove)
+                .service(templates::preview)
+                .service(templates::add)
+        )
+        .service(
+            web::scope("/offers")
+                .service(offers::show_all)
+                // Before `show`, which would take "pending" for a SKU
+                .service(offers::pending)
+                .service(offers::show)
+                .service(offers::update)
+                .service(offers::update_all)
+                .service(offers::feed)
+        )
+        .service(
+            web::scope("/repricing")
+                .service(repricing::rules)
+                .service(repricing::save_rule)
+                .service(repricing::remove_rule)
+                .service(repricing::run)
+                .service(repricing::audit)
+        )
+        .service(events::subscribe)
+        .service(metrics::show)
+        .service(docs::openapi_json)
+        .service(docs::docs)
+        .service(docs::swagger_ui())
+        .service(Files::new("/images", IMAGE_HOST.dir()));
 }
 
 #[actix_web::main]
@@ -58,15 +132,64 @@ async fn main() -> anyhow::Result<()> {
 
     let client = Client::builder().default_headers(headers).build()?;
 
+    // `kaspi-service reconcile` prints the report instead of serving
+    if std::env::args().nth(1).as_deref() == Some("reconcile") {
+        let report = RECONCILER.run(&client).await.map_err(anyhow::Error::msg)?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    match IMAGE_HOST.base_url() {
+        Some(base_url) => info!("Hosting images at {}", base_url),
+        None => log::warn!("Image hosting is disabled, IMAGES_BASE_URL is not a public HTTPS URL"),
+    }
+
+    WEBHOOKS.fill().await;
+    WEBHOOKS.spawn_retries();
+    info!("{} webhooks, {} deliveries to retry", WEBHOOKS.endpoints_len().await, WEBHOOKS.pending_len().await);
+
+    TEMPLATES.fill().await;
+    OFFERS.fill().await;
+    info!("{} offers, {} changed since the last feed", OFFERS.all().await.len(), OFFERS.pending().await.len());
+    REPRICER.fill().await;
+
+    // Repricing runs only on request unless REPRICING_INTERVAL is set
+    if let Some(interval) = dotenv::var("REPRICING_INTERVAL").ok().and_then(|i| i.parse::<u64>().ok()) {
+        spawn_repricing(Duration::from_secs(interval)).map_err(anyhow::Error::msg)?;
+        info!("Repricing {} offers every {} seconds", REPRICER.rules().await.len(), interval);
+    }
+
+    let autosave = dotenv::var("AUTOSAVE_INTERVAL")
+        .ok()
+        .and_then(|i| i.parse::<u64>().ok())
+        .unwrap_or(300);
+    spawn_autosave(Duration::from_secs(autosave));
+
+    let concurrency = dotenv::var("UPLOAD_CONCURRENCY")
+        .ok()
+        .and_then(|c| c.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_CONCURRENCY);
+    JOBS.start(concurrency, Arc::new(client.clone()));
+    info!("{} upload workers started", concurrency);
+
     HttpServer::new(move ||
         App::new()
             .wrap(Logger::default())
             .app_data(web::Data::new(client.clone()))
+            .app_data(web::JsonConfig::default().error_handler(json_error))
+            .app_data(web::QueryConfig::default().error_handler(query_error))
             .configure(init))
             .bind("localhost:8000")?
             .run()
             .await?;
 
+    // The server stops on SIGINT/SIGTERM, let the uploads in flight receive their codes
+    info!("Waiting for uploads in flight...");
+    let dropped = JOBS.shutdown().await;
+    if dropped > 0 {
+        log::warn!("{} queued products were not uploaded", dropped);
+    }
+
     spawn_save().await;
 
     Ok(())
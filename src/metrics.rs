@@ -0,0 +1,142 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::Mutex,
+    time::Duration,
+};
+use crate::{STORE, JOBS, RETRY_STATS};
+
+/// Upper bounds of the latency buckets, in seconds
+pub const LATENCY_BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+#[derive(Debug, Default, Clone)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS.iter()) {
+            if seconds <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct EndpointMetrics {
+    latency: Histogram,
+    errors: u64,
+}
+
+/// Latency and errors of the requests to Kaspi, per endpoint
+#[derive(Default)]
+pub struct Metrics {
+    endpoints: Mutex<BTreeMap<&'static str, EndpointMetrics>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn observe(&self, endpoint: &'static str, elapsed: Duration, failed: bool) {
+        let mut endpoints = self.endpoints.lock().expect("Metrics lock is poisoned");
+        let metrics = endpoints.entry(endpoint).or_default();
+
+        metrics.latency.observe(elapsed.as_secs_f64());
+        if failed {
+            metrics.errors += 1;
+        }
+    }
+
+    fn render_endpoints(&self, out: &mut String) {
+        let endpoints = self.endpoints.lock().expect("Metrics lock is poisoned").clone();
+
+        out.push_str("# HELP kaspi_request_duration_seconds Latency of requests to Kaspi\n");
+        out.push_str("# TYPE kaspi_request_duration_seconds histogram\n");
+        for (endpoint, metrics) in endpoints.iter() {
+            let latency = &metrics.latency;
+
+            for (count, bound) in latency.buckets.iter().zip(LATENCY_BUCKETS.iter()) {
+                writeln!(out, "kaspi_request_duration_seconds_bucket{{endpoint=\"{}\",le=\"{}\"}} {}", endpoint, bound, count).unwrap();
+            }
+            writeln!(out, "kaspi_request_duration_seconds_bucket{{endpoint=\"{}\",le=\"+Inf\"}} {}", endpoint, latency.count).unwrap();
+            writeln!(out, "kaspi_request_duration_seconds_sum{{endpoint=\"{}\"}} {}", endpoint, latency.sum).unwrap();
+            writeln!(out, "kaspi_request_duration_seconds_count{{endpoint=\"{}\"}} {}", endpoint, latency.count).unwrap();
+        }
+
+        out.push_str("# HELP kaspi_request_errors_total Failed requests to Kaspi\n");
+        out.push_str("# TYPE kaspi_request_errors_total counter\n");
+        for (endpoint, metrics) in endpoints.iter() {
+            writeln!(out, "kaspi_request_errors_total{{endpoint=\"{}\"}} {}", endpoint, metrics.errors).unwrap();
+        }
+    }
+
+    /// Renders every metric in the Prometheus text format
+    pub async fn render(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP kaspi_products Products in the store by status\n");
+        out.push_str("# TYPE kaspi_products gauge\n");
+        for (status, len) in [
+            ("UPLOADED", STORE.uploaded_len().await),
+            ("FINISHED", STORE.finished_len().await),
+            ("ABORTED", STORE.aborted_len().await),
+        ] {
+            writeln!(out, "kaspi_products{{status=\"{}\"}} {}", status, len).unwrap();
+        }
+
+        out.push_str("# HELP kaspi_upload_queue_depth Products waiting for an upload worker\n");
+        out.push_str("# TYPE kaspi_upload_queue_depth gauge\n");
+        writeln!(out, "kaspi_upload_queue_depth {}", JOBS.queued_len().await).unwrap();
+
+        out.push_str("# HELP kaspi_oldest_uploaded_age_seconds Age of the oldest code still UPLOADED\n");
+        out.push_str("# TYPE kaspi_oldest_uploaded_age_seconds gauge\n");
+        let age = STORE.oldest_uploaded_at().await
+            .map_or(0, |at| (chrono::Utc::now() - at).num_seconds().max(0));
+        writeln!(out, "kaspi_oldest_uploaded_age_seconds {}", age).unwrap();
+
+        self.render_endpoints(&mut out);
+
+        for (name, help, value) in [
+            ("kaspi_retries_total", "Requests to Kaspi repeated after a failure", RETRY_STATS.retries()),
+            ("kaspi_throttled_total", "Requests to Kaspi delayed by the rate limiter", RETRY_STATS.throttled()),
+            ("kaspi_failures_total", "Requests to Kaspi given up on", RETRY_STATS.failures()),
+        ] {
+            writeln!(out, "# HELP {} {}\n# TYPE {} counter\n{} {}", name, help, name, name, value).unwrap();
+        }
+
+        out
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_is_cumulative() {
+        let metrics = Metrics::new();
+
+        metrics.observe("import", Duration::from_millis(80), false);
+        metrics.observe("import", Duration::from_secs(3), true);
+        metrics.observe("import", Duration::from_secs(60), false);
+
+        let mut out = String::new();
+        metrics.render_endpoints(&mut out);
+
+        assert!(out.contains("kaspi_request_duration_seconds_bucket{endpoint=\"import\",le=\"0.05\"} 0\n"));
+        assert!(out.contains("kaspi_request_duration_seconds_bucket{endpoint=\"import\",le=\"0.1\"} 1\n"));
+        assert!(out.contains("kaspi_request_duration_seconds_bucket{endpoint=\"import\",le=\"5\"} 2\n"));
+        assert!(out.contains("kaspi_request_duration_seconds_bucket{endpoint=\"import\",le=\"+Inf\"} 3\n"));
+        assert!(out.contains("kaspi_request_duration_seconds_count{endpoint=\"import\"} 3\n"));
+        assert!(out.contains("kaspi_request_errors_total{endpoint=\"import\"} 1\n"));
+    }
+}
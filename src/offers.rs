@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use tokio::sync::Mutex;
+use crate::{
+    entities::product::Product,
+    json_processing::{read_json, save_json},
+};
+
+pub const OFFERS_FILE: &str = "offers.json";
+pub const DEFAULT_STORE_ID: &str = "PP1";
+
+/// Who changed the offer
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    Api,
+    Repricer,
+}
+
+/// Price in tenge and the number of items available
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+pub struct OfferUpdate {
+    pub price: u64,
+    pub stock: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct SkuOfferUpdate {
+    pub sku: String,
+    #[serde(flatten)]
+    pub offer: OfferUpdate,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct OfferChange {
+    pub at: DateTime<Utc>,
+    pub price: u64,
+    pub stock: u32,
+    pub source: Source,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct Offer {
+    pub sku: String,
+    pub price: u64,
+    pub stock: u32,
+    pub updated_at: DateTime<Utc>,
+    /// Price and stock in the last generated feed
+    pub published: Option<OfferUpdate>,
+    /// Changes, oldest first
+    #[serde(default)]
+    pub history: Vec<OfferChange>,
+}
+
+impl Offer {
+    pub fn current(&self) -> OfferUpdate {
+        OfferUpdate { price: self.price, stock: self.stock }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.published != Some(self.current())
+    }
+}
+
+/// Offer that differs from the last generated feed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct PendingChange {
+    pub sku: String,
+    /// None for offers never published
+    pub published: Option<OfferUpdate>,
+    pub current: OfferUpdate,
+}
+
+/// Prices and stock per SKU
+pub struct Offers {
+    file_name: String,
+    offers: Mutex<HashMap<String, Offer>>,
+}
+
+impl Default for Offers {
+    fn default() -> Self {
+        Self::new(OFFERS_FILE)
+    }
+}
+
+impl Offers {
+    pub fn new(file_name: &str) -> Self {
+        Self {
+            file_name: file_name.to_owned(),
+            offers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn fill(&self) {
+        let offers = read_json(&self.file_name).await.expect("Could not read offers file");
+        *self.offers.lock().await = offers
+            .into_iter()
+            .map(|o| serde_json::from_value::<Offer>(o).expect("Could not parse offer"))
+            .map(|o| (o.sku.clone(), o))
+            .collect();
+    }
+
+    pub async fn get(&self, sku: &str) -> Option<Offer> {
+        self.offers.lock().await.get(sku).cloned()
+    }
+
+    /// Sorted by SKU
+    pub async fn all(&self) -> Vec<Offer> {
+        sorted(&*self.offers.lock().await)
+    }
+
+    /// Stores the offers, keeping the history of the changed ones
+    /// Returns whether each one changed
+    pub async fn update(&self, updates: Vec<SkuOfferUpdate>, source: Source) -> Vec<(String, bool)> {
+        let updates = updates.into_iter().map(|u| (u.sku, u.offer.price, Some(u.offer.stock))).collect();
+        self.apply(updates, source).await
+    }
+
+    /// Sets the prices of the stored offers, keeping the stock they have at the moment
+    /// SKUs without an offer are not changed
+    pub async fn set_prices(&self, prices: Vec<(String, u64)>, source: Source) -> Vec<(String, bool)> {
+        let updates = prices.into_iter().map(|(sku, price)| (sku, price, None)).collect();
+        self.apply(updates, source).await
+    }
+
+    /// Stock left out keeps the current one
+    async fn apply(&self, updates: Vec<(String, u64, Option<u32>)>, source: Source) -> Vec<(String, bool)> {
+        let now = Utc::now();
+        let mut changed = Vec::new();
+
+        let mut offers = self.offers.lock().await;
+        for (sku, price, stock) in updates.into_iter() {
+            let Some(stock) = stock.or_else(|| offers.get(&sku).map(|o| o.stock)) else {
+                changed.push((sku, false));
+                continue;
+            };
+            let update = OfferUpdate { price, stock };
+            let change = OfferChange { at: now, price, stock, source };
+
+            let is_changed = match offers.get_mut(&sku) {
+                Some(offer) if offer.current() == update => false,
+                Some(offer) => {
+                    offer.price = update.price;
+                    offer.stock = update.stock;
+                    offer.updated_at = now;
+                    offer.history.push(change);
+                    true
+                }
+                None => {
+                    offers.insert(sku.clone(), Offer {
+                        sku: sku.clone(),
+                        price: update.price,
+                        stock: update.stock,
+                        updated_at: now,
+                        published: None,
+                        history: vec![change],
+                    });
+                    true
+                }
+            };
+            changed.push((sku, is_changed));
+        }
+
+        if changed.iter().any(|(_, c)| *c) {
+            self.save(&offers).await;
+        }
+        changed
+    }
+
+    /// Offers changed since the last feed, by SKU
+    pub async fn pending(&self) -> Vec<PendingChange> {
+        self.all()
+            .await
+            .into_iter()
+            .filter(Offer::is_pending)
+            .map(|o| PendingChange { sku: o.sku.clone(), published: o.published, current: o.current() })
+            .collect()
+    }
+
+    /// Marks every offer as published in a feed
+    /// Returns the offers of the feed
+    pub async fn publish(&self) -> Vec<Offer> {
+        let mut offers = self.offers.lock().await;
+        offers.values_mut().for_each(|o| o.published = Some(o.current()));
+
+        self.save(&offers).await;
+        sorted(&offers)
+    }
+
+    /// Called with the offers locked, so the file gets the newest ones and writes do not overlap
+    async fn save(&self, offers: &HashMap<String, Offer>) {
+        let json = serde_json::to_value(sorted(offers)).expect("Could not create Value");
+        if let Err(e) = save_json(&self.file_name, json).await {
+            log::error!("Could not save offers: {}", e);
+        }
+    }
+}
+
+fn sorted(offers: &HashMap<String, Offer>) -> Vec<Offer> {
+    let mut offers: Vec<Offer> = offers.values().cloned().collect();
+    offers.sort_by(|a, b| a.sku.cmp(&b.sku));
+    offers
+}
+
+/// Merchant the feed is generated for
+#[derive(Debug, Clone)]
+pub struct Merchant {
+    pub company: String,
+    pub merchant_id: String,
+    pub store_id: String,
+}
+
+impl Merchant {
+    /// Reads KASPI_COMPANY, KASPI_MERCHANT_ID and KASPI_STORE_ID
+    pub fn from_env() -> Self {
+        Self {
+            company: dotenv::var("KASPI_COMPANY").unwrap_or_default(),
+            merchant_id: dotenv::var("KASPI_MERCHANT_ID").unwrap_or_default(),
+            store_id: dotenv::var("KASPI_STORE_ID").unwrap_or_else(|_| DEFAULT_STORE_ID.to_owned()),
+        }
+    }
+}
+
+/// Price list in Kaspi's XML format
+/// Models and brands are taken from the products with the same SKU
+pub fn feed(merchant: &Merchant, offers: &[Offer], products: &HashMap<String, Product>, date: DateTime<Utc>) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str(&format!(
+        "<kaspi_catalog date=\"{}\" xmlns=\"kaspiShopping\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xsi:schemaLocation=\"kaspiShopping http://kaspi.kz/kaspishopping.xsd\">\n",
+        date.format("%Y-%m-%dT%H:%M:%S")
+    ));
+    xml.push_str(&format!("  <company>{}</company>\n", escape(&merchant.company)));
+    xml.push_str(&format!("  <merchantid>{}</merchantid>\n", escape(&merchant.merchant_id)));
+    xml.push_str("  <offers>\n");
+
+    for offer in offers.iter() {
+        let product = products.get(&offer.sku);
+
+        xml.push_str(&format!("    <offer sku=\"{}\">\n", escape(&offer.sku)));
+        xml.push_str(&format!("      <model>{}</model>\n", escape(product.map_or(&offer.sku, |p| p.title()))));
+        if let Some(product) = product {
+            xml.push_str(&format!("      <brand>{}</brand>\n", escape(product.brand())));
+        }
+        xml.push_str("      <availabilities>\n");
+        xml.push_str(&format!(
+            "        <availability available=\"{}\" storeId=\"{}\" stockCount=\"{}\"/>\n",
+            if offer.stock > 0 { "yes" } else { "no" },
+            escape(&merchant.store_id),
+            offer.stock
+        ));
+        xml.push_str("      </availabilities>\n");
+        xml.push_str(&format!("      <price>{}</price>\n", offer.price));
+        xml.push_str("    </offer>\n");
+    }
+
+    xml.push_str("  </offers>\n</kaspi_catalog>\n");
+    xml
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(sku: &str, price: u64, stock: u32) -> SkuOfferUpdate {
+        SkuOfferUpdate { sku: sku.to_owned(), offer: OfferUpdate { price, stock } }
+    }
+
+    #[actix_rt::test]
+    async fn pending_changes() {
+        let file_name = std::env::temp_dir().join(format!("offers-{}.json", uuid::Uuid::new_v4()));
+        let offers = Offers::new(file_name.to_str().unwrap());
+
+        let changed = offers.update(vec![update("A", 25000, 3), update("B", 18000, 0)], Source::Api).await;
+        assert_eq!(changed, vec![(String::from("A"), true), (String::from("B"), true)]);
+        assert_eq!(offers.pending().await.len(), 2);
+
+        offers.publish().await;
+        assert!(offers.pending().await.is_empty());
+
+        let changed = offers.update(vec![update("A", 25000, 3), update("B", 17500, 0)], Source::Api).await;
+        assert_eq!(changed, vec![(String::from("A"), false), (String::from("B"), true)]);
+        assert_eq!(offers.pending().await, vec![PendingChange {
+            sku: String::from("B"),
+            published: Some(OfferUpdate { price: 18000, stock: 0 }),
+            current: OfferUpdate { price: 17500, stock: 0 },
+        }]);
+        assert_eq!(offers.get("B").await.unwrap().history.len(), 2);
+
+        // Changes survive a restart
+        let reloaded = Offers::new(file_name.to_str().unwrap());
+        reloaded.fill().await;
+        assert_eq!(reloaded.all().await, offers.all().await);
+
+        std::fs::remove_file(file_name).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn prices_keep_stock() {
+        let file_name = std::env::temp_dir().join(format!("offers-{}.json", uuid::Uuid::new_v4()));
+        let offers = Offers::new(file_name.to_str().unwrap());
+
+        offers.update(vec![update("A", 25000, 3)], Source::Api).await;
+        // Stock changed after the new price was worked out
+        offers.update(vec![update("A", 25000, 1)], Source::Api).await;
+
+        let changed = offers.set_prices(vec![(String::from("A"), 24000), (String::from("B"), 1000)], Source::Repricer).await;
+        assert_eq!(changed, vec![(String::from("A"), true), (String::from("B"), false)]);
+        assert_eq!(offers.get("A").await.unwrap().current(), OfferUpdate { price: 24000, stock: 1 });
+        assert_eq!(offers.get("B").await, None);
+
+        std::fs::remove_file(file_name).unwrap();
+    }
+
+    #[test]
+    fn feed_xml() {
+        let merchant = Merchant {
+            company: String::from("Pariki & Co"),
+            merchant_id: String::from("12345"),
+            store_id: String::from("PP1"),
+        };
+        let offer = Offer {
+            sku: String::from("LACEFRONT-27"),
+            price: 25000,
+            stock: 0,
+            updated_at: Utc::now(),
+            published: None,
+            history: Vec::new(),
+        };
+
+        let xml = feed(&merchant, &[offer], &HashMap::new(), Utc::now());
+
+        assert!(xml.contains("<company>Pariki &amp; Co</company>"));
+        assert!(xml.contains("<offer sku=\"LACEFRONT-27\">"));
+        assert!(xml.contains("<model>LACEFRONT-27</model>"));
+        assert!(xml.contains("available=\"no\" storeId=\"PP1\" stockCount=\"0\""));
+        assert!(xml.contains("<price>25000</price>"));
+    }
+}
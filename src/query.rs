@@ -0,0 +1,188 @@
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+use chrono::{DateTime, Duration, Utc};
+use crate::entities::{
+    product::Product,
+    timestamps::Timestamps,
+    upload_result::{Status, UploadResult},
+};
+
+pub const DEFAULT_LIMIT: usize = 50;
+pub const MAX_LIMIT: usize = 1000;
+
+#[derive(Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    Sku,
+    CreatedAt,
+    UploadedAt,
+    CheckedAt,
+    FinishedAt,
+}
+
+#[derive(Deserialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Order {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Everything known about a record that can be filtered and sorted by
+pub struct Entry<'a> {
+    pub product: &'a Product,
+    pub status: Status,
+    pub timestamps: Timestamps,
+    pub result: Option<&'a UploadResult>,
+}
+
+#[derive(Deserialize, IntoParams, Debug, Default)]
+#[into_params(parameter_in = Query)]
+pub struct ListQuery {
+    pub status: Option<Status>,
+    pub category: Option<String>,
+    pub brand: Option<String>,
+    pub sku_prefix: Option<String>,
+    /// Part of the SKU, in any case
+    pub sku: Option<String>,
+    pub has_errors: Option<bool>,
+    /// Only records waiting for Kaspi at least this many seconds
+    pub uploaded_for: Option<i64>,
+    #[serde(default)]
+    pub sort: SortKey,
+    #[serde(default)]
+    pub order: Order,
+    /// Starts from 1
+    pub page: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+impl ListQuery {
+    pub fn matches(&self, entry: &Entry, now: DateTime<Utc>) -> bool {
+        let product = entry.product;
+
+        if self.status.is_some_and(|s| s != entry.status) {
+            return false;
+        }
+        if self.category.as_ref().is_some_and(|c| !c.eq_ignore_ascii_case(product.category())) {
+            return false;
+        }
+        if self.brand.as_ref().is_some_and(|b| !b.eq_ignore_ascii_case(product.brand())) {
+            return false;
+        }
+        if self.sku_prefix.as_ref().is_some_and(|p| !product.sku().starts_with(p.as_str())) {
+            return false;
+        }
+        if self.sku.as_ref().is_some_and(|s| !product.sku().to_lowercase().contains(&s.to_lowercase())) {
+            return false;
+        }
+        if let Some(has_errors) = self.has_errors {
+            if entry.result.is_some_and(|r| r.errors() > 0) != has_errors {
+                return false;
+            }
+        }
+        if let Some(seconds) = self.uploaded_for {
+            let waiting = entry.status == Status::UPLOADED && entry.timestamps
+                .upload_age(now)
+                .is_some_and(|age| age >= Duration::seconds(seconds));
+
+            if !waiting {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Sorts the entries, ties are broken by SKU
+    pub fn sort<T>(&self, entries: &mut [(Entry, T)]) {
+        entries.sort_by(|(a, _), (b, _)| {
+            let ordering = match self.sort {
+                SortKey::Sku => a.product.sku().cmp(b.product.sku()),
+                SortKey::CreatedAt => a.timestamps.created_at.cmp(&b.timestamps.created_at),
+                SortKey::UploadedAt => a.timestamps.uploaded_at.cmp(&b.timestamps.uploaded_at),
+                SortKey::CheckedAt => a.timestamps.checked_at.cmp(&b.timestamps.checked_at),
+                SortKey::FinishedAt => a.timestamps.finished_at.cmp(&b.timestamps.finished_at),
+            }.then_with(|| a.product.sku().cmp(b.product.sku()));
+
+            match self.order {
+                Order::Asc => ordering,
+                Order::Desc => ordering.reverse(),
+            }
+        });
+    }
+
+    pub fn page(&self) -> usize {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+
+    /// Returns the range of the requested page within `total` entries
+    pub fn range(&self, total: usize) -> std::ops::Range<usize> {
+        let start = (self.page() - 1).saturating_mul(self.limit()).min(total);
+        let end = start.saturating_add(self.limit()).min(total);
+
+        start..end
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn product(sku: &str, brand: &str) -> Product {
+        let mut product = testing::product(sku, "Title");
+        product.set_brand(brand.to_owned());
+        product
+    }
+
+    fn query(json: serde_json::Value) -> ListQuery {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn filters() {
+        let now = Utc::now();
+        let product = product("LACEFRONT-27", "ParikiAlmaty");
+        let result = UploadResult::new(2, 0, 0, 1, Vec::new());
+        let entry = Entry {
+            product: &product,
+            status: Status::UPLOADED,
+            timestamps: Timestamps { uploaded_at: Some(now - Duration::hours(3)), ..Timestamps::default() },
+            result: Some(&result),
+        };
+
+        assert!(query(serde_json::json!({})).matches(&entry, now));
+        assert!(query(serde_json::json!({ "status": "UPLOADED", "brand": "parikialmaty", "category": "PARIKI" })).matches(&entry, now));
+        assert!(query(serde_json::json!({ "sku_prefix": "LACE", "sku": "front" })).matches(&entry, now));
+        assert!(query(serde_json::json!({ "has_errors": true, "uploaded_for": 7200 })).matches(&entry, now));
+
+        assert!(!query(serde_json::json!({ "status": "FINISHED" })).matches(&entry, now));
+        assert!(!query(serde_json::json!({ "sku_prefix": "front" })).matches(&entry, now));
+        assert!(!query(serde_json::json!({ "has_errors": false })).matches(&entry, now));
+        assert!(!query(serde_json::json!({ "uploaded_for": 36000 })).matches(&entry, now));
+    }
+
+    #[test]
+    fn sort_and_paginate() {
+        let products = [product("B", "x"), product("A", "x"), product("C", "x")];
+        let mut entries: Vec<(Entry, usize)> = products.iter().enumerate()
+            .map(|(i, product)| (Entry { product, status: Status::FINISHED, timestamps: Timestamps::default(), result: None }, i))
+            .collect();
+
+        query(serde_json::json!({ "order": "desc" })).sort(&mut entries);
+        let order: Vec<usize> = entries.iter().map(|(_, i)| *i).collect();
+        assert_eq!(order, vec![2, 0, 1]);
+
+        let paged = query(serde_json::json!({ "page": 2, "limit": 2 }));
+        assert_eq!(paged.range(3), 2..3);
+        assert_eq!(paged.range(1), 1..1);
+        assert_eq!(query(serde_json::json!({ "limit": 0 })).range(3), 0..1);
+    }
+}
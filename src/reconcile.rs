@@ -0,0 +1,304 @@
+use uuid::Uuid;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, atomic::{AtomicBool, Ordering}},
+};
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+use tokio::sync::Mutex;
+use crate::{STORE, retry, diff::ProductDiff, entities::product::Product};
+
+pub const DEFAULT_CATALOG_URL: &str = "https://kaspi.kz/shop/api/products/merchant";
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+/// Guards against an endpoint that never returns a short page
+pub const MAX_PAGES: usize = 10_000;
+
+/// Local product that Kaspi does not list
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct MissingRemotely {
+    pub id: Uuid,
+    pub sku: String,
+}
+
+/// Product listed by both sides with different contents
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct Difference {
+    pub id: Uuid,
+    pub sku: String,
+    /// Fields of the product whose values differ
+    pub fields: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct ReconcileReport {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    /// Number of SKUs in the store
+    pub local: usize,
+    /// Number of SKUs in the merchant catalog
+    pub remote: usize,
+    pub missing_remotely: Vec<MissingRemotely>,
+    /// SKUs that only Kaspi lists
+    pub missing_locally: Vec<String>,
+    pub differs: Vec<Difference>,
+}
+
+/// Last reconciliation, or the reason it failed
+#[derive(Debug, Clone)]
+pub enum LastRun {
+    Done(ReconcileReport),
+    Failed(String),
+}
+
+/// Compares the merchant catalog on Kaspi with the store
+pub struct Reconciler {
+    url: String,
+    page_size: usize,
+    running: AtomicBool,
+    last: Mutex<Option<LastRun>>,
+}
+
+impl Reconciler {
+    pub fn new(url: &str, page_size: usize) -> Self {
+        Self {
+            url: url.to_owned(),
+            page_size: page_size.max(1),
+            running: AtomicBool::new(false),
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Reads KASPI_CATALOG_URL and KASPI_CATALOG_PAGE_SIZE
+    pub fn from_env() -> Self {
+        Self::new(
+            &dotenv::var("KASPI_CATALOG_URL").unwrap_or_else(|_| DEFAULT_CATALOG_URL.to_owned()),
+            dotenv::var("KASPI_CATALOG_PAGE_SIZE")
+                .ok()
+                .and_then(|s| s.parse::<usize>().ok())
+                .unwrap_or(DEFAULT_PAGE_SIZE),
+        )
+    }
+
+    /// Pulls every page of the catalog
+    /// A page is either an array of products or an object with them under `data`
+    pub async fn fetch(&self, client: &Client) -> Result<Vec<Value>, String> {
+        let mut products = Vec::new();
+
+        for page in 0..MAX_PAGES {
+            let response = retry::send("catalog", || {
+                client
+                    .get(&self.url)
+                    .query(&[("page", page), ("size", self.page_size)])
+            }, true).await?;
+
+            let body = response.json::<Value>()
+                .await
+                .map_err(|e| format!("Could not parse catalog page {}: {}", page, e))?;
+
+            let items = match body {
+                Value::Array(items) => items,
+                Value::Object(mut map) => match map.remove("data") {
+                    Some(Value::Array(items)) => items,
+                    _ => return Err(format!("Catalog page {} has no data", page)),
+                },
+                _ => return Err(format!("Catalog page {} is not a list", page)),
+            };
+
+            let last = items.len() < self.page_size;
+            products.extend(items);
+            if last {
+                return Ok(products);
+            }
+        }
+
+        Err(format!("Catalog has more than {} pages", MAX_PAGES))
+    }
+
+    /// Fetches the catalog and compares it with the store
+    pub async fn run(&self, client: &Client) -> Result<ReconcileReport, String> {
+        let started_at = Utc::now();
+        let remote = self.fetch(client).await?;
+        // Aborted versions never reached the catalog
+        let local = STORE.latest_versions_on_kaspi().await;
+
+        let mut report = compare(&local, &remote);
+        report.started_at = started_at;
+        report.finished_at = Utc::now();
+
+        Ok(report)
+    }
+
+    /// Runs a reconciliation in the background
+    /// Returns false if one is already running
+    pub fn start(&'static self, client: Arc<Client>) -> bool {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+
+        actix_rt::spawn(async move {
+            let last = match self.run(&client).await {
+                Ok(report) => {
+                    log::info!(
+                        "Reconciled: {} missing remotely, {} missing locally, {} differ",
+                        report.missing_remotely.len(), report.missing_locally.len(), report.differs.len()
+                    );
+                    LastRun::Done(report)
+                }
+                Err(e) => {
+                    log::error!("Could not reconcile: {}", e);
+                    LastRun::Failed(e)
+                }
+            };
+
+            *self.last.lock().await = Some(last);
+            self.running.store(false, Ordering::SeqCst);
+        });
+
+        true
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    pub async fn last(&self) -> Option<LastRun> {
+        self.last.lock().await.clone()
+    }
+}
+
+/// Compares the local products with the catalog by SKU
+/// Catalog entries without a SKU are skipped
+pub fn compare(local: &[(Uuid, Product)], remote: &[Value]) -> ReconcileReport {
+    let remote: BTreeMap<&str, &Value> = remote
+        .iter()
+        .filter_map(|item| item.get("sku").and_then(Value::as_str).map(|sku| (sku, item)))
+        .collect();
+    let local_skus: BTreeSet<&str> = local.iter().map(|(_, p)| p.sku().as_str()).collect();
+
+    let mut missing_remotely = Vec::new();
+    let mut differs = Vec::new();
+
+    for (id, product) in local {
+        let Some(item) = remote.get(product.sku().as_str()) else {
+            missing_remotely.push(MissingRemotely { id: *id, sku: product.sku().to_owned() });
+            continue;
+        };
+
+        let fields = different_fields(product, item);
+        if !fields.is_empty() {
+            differs.push(Difference { id: *id, sku: product.sku().to_owned(), fields });
+        }
+    }
+
+    missing_remotely.sort_by(|a, b| a.sku.cmp(&b.sku));
+    differs.sort_by(|a, b| a.sku.cmp(&b.sku));
+
+    let now = Utc::now();
+    ReconcileReport {
+        started_at: now,
+        finished_at: now,
+        local: local_skus.len(),
+        remote: remote.len(),
+        missing_remotely,
+        missing_locally: remote.keys().filter(|sku| !local_skus.contains(*sku)).map(|sku| sku.to_string()).collect(),
+        differs,
+    }
+}
+
+/// Fields of the local product whose values the catalog does not have
+/// Compared as `ProductDiff` does, so reordered lists and whitespace are not differences
+/// Fields only the catalog has, such as its own ids, are not compared
+fn different_fields(local: &Product, remote: &Value) -> Vec<String> {
+    if let Ok(remote) = serde_json::from_value::<Product>(remote.clone()) {
+        return ProductDiff::between(local, &remote).changed_fields();
+    }
+
+    // Entries that can not be read as products are missing fields or have them mistyped
+    let local = serde_json::to_value(local).expect("Could not convert to json");
+    local.as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(field, value)| remote.get(field.as_str()) != Some(*value))
+        .map(|(field, _)| field.to_owned())
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use actix_web::{web, HttpResponse};
+    use crate::testing::{product, product_json, serve};
+
+    fn local(sku: &str, title: &str) -> (Uuid, Product) {
+        (Uuid::new_v5(&Uuid::NAMESPACE_URL, sku.as_bytes()), product(sku, title))
+    }
+
+    #[test]
+    fn compare_by_sku() {
+        let mut with_images = product_json("E", "Title");
+        with_images["images"] = json!([{ "url": "https://a.kz/1.jpg" }, { "url": "https://a.kz/2.jpg" }]);
+        let local = vec![
+            local("A", "Title"), local("B", "Title"), local("C", "Title"), local("F", "Title"),
+            (Uuid::new_v5(&Uuid::NAMESPACE_URL, b"E"), serde_json::from_value(with_images.clone()).unwrap()),
+        ];
+
+        let mut changed = product_json("B", "Other title");
+        changed["id"] = json!(42);
+        // Kaspi may list the images in another order and reflow the text
+        let mut reordered = with_images;
+        reordered["title"] = json!("  Title ");
+        reordered["images"] = json!([{ "url": "https://a.kz/2.jpg" }, { "url": "https://a.kz/1.jpg" }]);
+        let mut partial = product_json("F", "Title");
+        partial.as_object_mut().unwrap().remove("description");
+        let remote = vec![product_json("A", "Title"), changed, product_json("D", "Title"), reordered, partial, json!({ "title": "No sku" })];
+
+        let report = compare(&local, &remote);
+
+        assert_eq!((report.local, report.remote), (5, 5));
+        assert_eq!(report.missing_remotely, vec![MissingRemotely { id: local[2].0, sku: String::from("C") }]);
+        assert_eq!(report.missing_locally, vec![String::from("D")]);
+        assert_eq!(report.differs, vec![
+            Difference { id: local[1].0, sku: String::from("B"), fields: vec![String::from("title")] },
+            Difference { id: local[3].0, sku: String::from("F"), fields: vec![String::from("description")] },
+        ]);
+    }
+
+    #[actix_rt::test]
+    async fn fetch_every_page() {
+        #[derive(Deserialize)]
+        struct Page {
+            page: usize,
+            size: usize,
+        }
+
+        let (base_url, server) = serve(|config| {
+            config.route("/catalog", web::get().to(|query: web::Query<Page>| async move {
+                // Five products in total
+                let items: Vec<Value> = (query.page * query.size..((query.page + 1) * query.size).min(5))
+                    .map(|i| product_json(&format!("SKU-{}", i), "Title"))
+                    .collect();
+
+                if query.page == 0 {
+                    HttpResponse::Ok().json(items)
+                } else {
+                    HttpResponse::Ok().json(json!({ "data": items }))
+                }
+            }));
+        });
+
+        let reconciler = Reconciler::new(&format!("{}/catalog", base_url), 2);
+        let products = reconciler.fetch(&Client::new()).await.unwrap();
+        assert_eq!(products.len(), 5);
+        assert_eq!(products[4]["sku"], "SKU-4");
+
+        let missing = Reconciler::new(&format!("{}/missing", base_url), 2);
+        assert!(missing.fetch(&Client::new()).await.unwrap_err().contains("404"));
+
+        server.stop(false).await;
+    }
+}
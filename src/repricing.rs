@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use tokio::sync::Mutex;
+use crate::{
+    offers::{Offers, Source},
+    json_processing::{read_json, save_json},
+};
+
+pub const RULES_FILE: &str = "repricing_rules.json";
+pub const AUDIT_FILE: &str = "repricing_audit.json";
+pub const DEFAULT_COMPETITORS_FILE: &str = "competitors.json";
+/// Oldest audit entries are dropped past this number
+pub const MAX_AUDIT: usize = 10_000;
+
+/// How the price follows the competitors
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum Rule {
+    MatchLowest,
+    /// Undercut the lowest price by `amount` tenge
+    BeatBy { amount: u64 },
+    /// Be the `position`-th cheapest offer, starting from 1
+    Position { position: usize },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct RepricingRule {
+    /// Taken from the path when the rule is stored
+    #[serde(default)]
+    pub sku: String,
+    pub min_price: u64,
+    pub max_price: u64,
+    #[serde(flatten)]
+    pub rule: Rule,
+}
+
+impl RepricingRule {
+    /// Price the rule asks for, within the bounds
+    /// Without competitors the price is the highest allowed
+    pub fn price(&self, competitors: &[u64]) -> u64 {
+        let mut prices = competitors.to_vec();
+        prices.sort_unstable();
+
+        let target = match (self.rule, prices.first()) {
+            (_, None) => self.max_price,
+            (Rule::MatchLowest, Some(lowest)) => *lowest,
+            (Rule::BeatBy { amount }, Some(lowest)) => lowest.saturating_sub(amount),
+            // Nobody to stay behind
+            (Rule::Position { position }, Some(_)) if position > prices.len() => self.max_price,
+            (Rule::Position { position }, Some(_)) => prices[position.max(1) - 1].saturating_sub(1),
+        };
+
+        target.clamp(self.min_price, self.max_price.max(self.min_price))
+    }
+}
+
+/// Offer of another merchant
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct CompetitorOffer {
+    pub sku: String,
+    pub merchant: String,
+    pub price: u64,
+}
+
+/// Where the competitor offers come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompetitorSource {
+    File(String),
+    Http(String),
+}
+
+impl CompetitorSource {
+    /// Reads REPRICING_SOURCE, either a URL or a file of competitor offers
+    pub fn from_env() -> Self {
+        let source = dotenv::var("REPRICING_SOURCE").unwrap_or_else(|_| DEFAULT_COMPETITORS_FILE.to_owned());
+
+        if source.starts_with("http://") || source.starts_with("https://") {
+            Self::Http(source)
+        } else {
+            Self::File(source)
+        }
+    }
+
+    pub async fn fetch(&self, client: &Client) -> Result<Vec<CompetitorOffer>, String> {
+        let offers: Vec<serde_json::Value> = match self {
+            Self::File(file_name) => read_json(file_name).await.map_err(|e| e.to_string())?,
+            Self::Http(url) => client
+                .get(url)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| e.to_string())?
+                .json()
+                .await
+                .map_err(|e| format!("Could not parse competitor offers: {}", e))?,
+        };
+
+        offers
+            .into_iter()
+            .map(|o| serde_json::from_value(o).map_err(|e| format!("Could not parse competitor offer: {}", e)))
+            .collect()
+    }
+}
+
+/// Price change made by the repricer
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct RepriceAudit {
+    pub at: DateTime<Utc>,
+    pub sku: String,
+    pub old_price: u64,
+    pub new_price: u64,
+    pub rule: Rule,
+    /// Competitor prices the decision was made with, lowest first
+    pub competitors: Vec<u64>,
+}
+
+/// Result of a repricing for one rule
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct RepriceOutcome {
+    pub sku: String,
+    /// None if the SKU has no offer to reprice
+    pub old_price: Option<u64>,
+    pub new_price: Option<u64>,
+    pub changed: bool,
+}
+
+pub struct Repricer {
+    rules_file: String,
+    audit_file: String,
+    source: CompetitorSource,
+    // Competitor sources are not Kaspi, so they get no API token
+    client: Client,
+    rules: Mutex<HashMap<String, RepricingRule>>,
+    audit: Mutex<Vec<RepriceAudit>>,
+}
+
+impl Repricer {
+    pub fn new(rules_file: &str, audit_file: &str, source: CompetitorSource) -> Self {
+        Self {
+            rules_file: rules_file.to_owned(),
+            audit_file: audit_file.to_owned(),
+            source,
+            client: Client::new(),
+            rules: Mutex::new(HashMap::new()),
+            audit: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(RULES_FILE, AUDIT_FILE, CompetitorSource::from_env())
+    }
+
+    pub async fn fill(&self) {
+        let rules = read_json(&self.rules_file).await.expect("Could not read repricing rules");
+        *self.rules.lock().await = rules
+            .into_iter()
+            .map(|r| serde_json::from_value::<RepricingRule>(r).expect("Could not parse repricing rule"))
+            .map(|r| (r.sku.clone(), r))
+            .collect();
+
+        let audit = read_json(&self.audit_file).await.expect("Could not read repricing audit");
+        *self.audit.lock().await = audit
+            .into_iter()
+            .filter_map(|a| serde_json::from_value(a).ok())
+            .collect();
+    }
+
+    /// Sorted by SKU
+    pub async fn rules(&self) -> Vec<RepricingRule> {
+        sorted(&*self.rules.lock().await)
+    }
+
+    pub async fn insert_rule(&self, rule: RepricingRule) -> Option<RepricingRule> {
+        let mut rules = self.rules.lock().await;
+        let old = rules.insert(rule.sku.clone(), rule);
+        self.save_rules(&rules).await;
+        old
+    }
+
+    pub async fn remove_rule(&self, sku: &str) -> Option<RepricingRule> {
+        let mut rules = self.rules.lock().await;
+        let old = rules.remove(sku);
+        if old.is_some() {
+            self.save_rules(&rules).await;
+        }
+        old
+    }
+
+    /// Newest first
+    pub async fn audit(&self, limit: usize) -> Vec<RepriceAudit> {
+        self.audit.lock().await.iter().rev().take(limit).cloned().collect()
+    }
+
+    /// Sets the prices of the offers with rules from the competitor offers
+    /// Offers of `own_merchant` are not competitors
+    pub async fn run(&self, offers: &Offers, own_merchant: &str) -> Result<Vec<RepriceOutcome>, String> {
+        // Own offers would be taken for competitors and undercut on every run
+        if own_merchant.trim().is_empty() {
+            return Err(String::from("Merchant id is not set, own offers can not be told from competitors"));
+        }
+
+        let mut competitors: HashMap<String, Vec<u64>> = HashMap::new();
+        for offer in self.source.fetch(&self.client).await?.into_iter() {
+            if offer.merchant != own_merchant {
+                competitors.entry(offer.sku).or_default().push(offer.price);
+            }
+        }
+
+        let now = Utc::now();
+        let mut outcomes = Vec::new();
+        let mut updates = Vec::new();
+        let mut audit = Vec::new();
+
+        for rule in self.rules().await.into_iter() {
+            let Some(offer) = offers.get(&rule.sku).await else {
+                outcomes.push(RepriceOutcome { sku: rule.sku, old_price: None, new_price: None, changed: false });
+                continue;
+            };
+
+            let mut prices = competitors.remove(&rule.sku).unwrap_or_default();
+            prices.sort_unstable();
+            let price = rule.price(&prices);
+
+            if price != offer.price {
+                updates.push((rule.sku.clone(), price));
+                audit.push(RepriceAudit {
+                    at: now,
+                    sku: rule.sku.clone(),
+                    old_price: offer.price,
+                    new_price: price,
+                    rule: rule.rule,
+                    competitors: prices,
+                });
+            }
+
+            outcomes.push(RepriceOutcome {
+                sku: rule.sku,
+                old_price: Some(offer.price),
+                new_price: Some(price),
+                changed: price != offer.price,
+            });
+        }
+
+        if !updates.is_empty() {
+            offers.set_prices(updates, Source::Repricer).await;
+
+            let mut log = self.audit.lock().await;
+            log.extend(audit);
+            let excess = log.len().saturating_sub(MAX_AUDIT);
+            log.drain(..excess);
+
+            self.save_audit(&log).await;
+        }
+
+        Ok(outcomes)
+    }
+
+    /// Both saves run under the lock they are given, one run or rule change is written at a time
+    async fn save_rules(&self, rules: &HashMap<String, RepricingRule>) {
+        let json = serde_json::to_value(sorted(rules)).expect("Could not create Value");
+        if let Err(e) = save_json(&self.rules_file, json).await {
+            log::error!("Could not save repricing rules: {}", e);
+        }
+    }
+
+    async fn save_audit(&self, audit: &[RepriceAudit]) {
+        let json = serde_json::to_value(audit).expect("Could not create Value");
+        if let Err(e) = save_json(&self.audit_file, json).await {
+            log::error!("Could not save repricing audit: {}", e);
+        }
+    }
+}
+
+fn sorted(rules: &HashMap<String, RepricingRule>) -> Vec<RepricingRule> {
+    let mut rules: Vec<RepricingRule> = rules.values().cloned().collect();
+    rules.sort_by(|a, b| a.sku.cmp(&b.sku));
+    rules
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offers::{OfferUpdate, SkuOfferUpdate};
+    use serde_json::json;
+
+    fn rule(rule: serde_json::Value) -> RepricingRule {
+        let mut value = json!({ "sku": "LACEFRONT-27", "min_price": 20000, "max_price": 30000 });
+        value.as_object_mut().unwrap().extend(rule.as_object().unwrap().clone());
+
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn rules_within_bounds() {
+        let competitors = [26000, 24000, 28000];
+
+        assert_eq!(rule(json!({ "rule": "match_lowest" })).price(&competitors), 24000);
+        assert_eq!(rule(json!({ "rule": "beat_by", "amount": 100 })).price(&competitors), 23900);
+        assert_eq!(rule(json!({ "rule": "beat_by", "amount": 5000 })).price(&competitors), 20000);
+        assert_eq!(rule(json!({ "rule": "position", "position": 2 })).price(&competitors), 25999);
+        assert_eq!(rule(json!({ "rule": "position", "position": 4 })).price(&competitors), 30000);
+        assert_eq!(rule(json!({ "rule": "match_lowest" })).price(&[]), 30000);
+        assert_eq!(rule(json!({ "rule": "match_lowest" })).price(&[40000]), 30000);
+    }
+
+    #[test]
+    fn sku_is_optional() {
+        let rule: RepricingRule = serde_json::from_value(json!({
+            "min_price": 20000,
+            "max_price": 30000,
+            "rule": "match_lowest"
+        })).unwrap();
+        assert_eq!(rule.sku, "");
+    }
+
+    #[actix_rt::test]
+    async fn reprice_offers() {
+        let dir = std::env::temp_dir().join(format!("kaspi-service-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_owned();
+
+        std::fs::write(path("competitors.json"), json!([
+            { "sku": "LACEFRONT-27", "merchant": "other", "price": 24000 },
+            { "sku": "LACEFRONT-27", "merchant": "pariki", "price": 21000 },
+            { "sku": "BOB-10", "merchant": "other", "price": 15000 }
+        ]).to_string()).unwrap();
+
+        let offers = Offers::new(&path("offers.json"));
+        offers.update(vec![SkuOfferUpdate {
+            sku: String::from("LACEFRONT-27"),
+            offer: OfferUpdate { price: 25000, stock: 3 },
+        }], Source::Api).await;
+
+        let repricer = Repricer::new(&path("rules.json"), &path("audit.json"), CompetitorSource::File(path("competitors.json")));
+        repricer.insert_rule(rule(json!({ "rule": "beat_by", "amount": 100 }))).await;
+        repricer.insert_rule(RepricingRule { sku: String::from("BOB-10"), ..rule(json!({ "rule": "match_lowest" })) }).await;
+
+        let outcomes = repricer.run(&offers, "pariki").await.unwrap();
+
+        assert_eq!(outcomes, vec![
+            RepriceOutcome { sku: String::from("BOB-10"), old_price: None, new_price: None, changed: false },
+            RepriceOutcome { sku: String::from("LACEFRONT-27"), old_price: Some(25000), new_price: Some(23900), changed: true },
+        ]);
+
+        let offer = offers.get("LACEFRONT-27").await.unwrap();
+        assert_eq!((offer.price, offer.stock), (23900, 3));
+        assert_eq!(offer.history.last().unwrap().source, Source::Repricer);
+
+        let audit = repricer.audit(10).await;
+        assert_eq!((audit[0].old_price, audit[0].new_price, audit[0].competitors.clone()), (25000, 23900, vec![24000]));
+
+        // A second run finds nothing to change
+        assert!(!repricer.run(&offers, "pariki").await.unwrap()[1].changed);
+        assert_eq!(repricer.audit(10).await.len(), 1);
+
+        // Without the merchant id its own offer would count as the lowest
+        assert!(repricer.run(&offers, "").await.unwrap_err().contains("Merchant id"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
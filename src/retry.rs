@@ -0,0 +1,238 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+use rand::Rng;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use tokio::sync::Mutex;
+use crate::{RETRY_POLICY, RATE_LIMITER, RETRY_STATS, METRICS};
+
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+pub const DEFAULT_RATE: f64 = 5.0;
+pub const DEFAULT_BURST: f64 = 10.0;
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    dotenv::var(key)
+        .ok()
+        .and_then(|v| v.parse::<T>().ok())
+        .unwrap_or(default)
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_ATTEMPTS, Duration::from_millis(200), Duration::from_secs(10))
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, base_delay: Duration, max_delay: Duration) -> Self {
+        Self { max_attempts: max_attempts.max(1), base_delay, max_delay }
+    }
+
+    /// Reads KASPI_MAX_ATTEMPTS, falling back to the defaults
+    pub fn from_env() -> Self {
+        Self {
+            max_attempts: env_or("KASPI_MAX_ATTEMPTS", DEFAULT_MAX_ATTEMPTS).max(1),
+            ..Self::default()
+        }
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Upper bound of the delay after the given attempt (starting from 1)
+    pub fn ceiling(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+
+        self.base_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |d| d.min(self.max_delay))
+    }
+
+    /// Exponential backoff with full jitter
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self.ceiling(attempt).as_millis() as u64;
+
+        Duration::from_millis(rand::thread_rng().gen_range(0..=ceiling))
+    }
+}
+
+/// Token bucket shared by every request to Kaspi
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64, burst: f64) -> Self {
+        let burst = burst.max(1.0);
+
+        Self {
+            rate: rate.max(f64::MIN_POSITIVE),
+            burst,
+            bucket: Mutex::new((burst, Instant::now())),
+        }
+    }
+
+    /// Reads KASPI_RATE_LIMIT (requests per second) and KASPI_BURST
+    pub fn from_env() -> Self {
+        Self::new(env_or("KASPI_RATE_LIMIT", DEFAULT_RATE), env_or("KASPI_BURST", DEFAULT_BURST))
+    }
+
+    /// Takes a token if one is available
+    /// Otherwise, returns how long to wait for the next one
+    pub async fn try_acquire(&self) -> Result<(), Duration> {
+        let mut bucket = self.bucket.lock().await;
+        let (tokens, last) = *bucket;
+
+        let now = Instant::now();
+        let tokens = (tokens + now.duration_since(last).as_secs_f64() * self.rate).min(self.burst);
+
+        if tokens >= 1.0 {
+            *bucket = (tokens - 1.0, now);
+            Ok(())
+        } else {
+            *bucket = (tokens, now);
+            Err(Duration::from_secs_f64((1.0 - tokens) / self.rate))
+        }
+    }
+
+    /// Waits until a token is available
+    pub async fn acquire(&self) {
+        let mut throttled = false;
+
+        while let Err(wait) = self.try_acquire().await {
+            if !throttled {
+                throttled = true;
+                RETRY_STATS.throttled.fetch_add(1, Ordering::Relaxed);
+            }
+            actix_rt::time::sleep(wait).await;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct RetryStats {
+    retries: AtomicUsize,
+    throttled: AtomicUsize,
+    failures: AtomicUsize,
+}
+
+impl RetryStats {
+    /// Requests repeated after a failed attempt
+    pub fn retries(&self) -> usize {
+        self.retries.load(Ordering::Relaxed)
+    }
+
+    /// Requests delayed by the rate limiter
+    pub fn throttled(&self) -> usize {
+        self.throttled.load(Ordering::Relaxed)
+    }
+
+    /// Requests given up on
+    pub fn failures(&self) -> usize {
+        self.failures.load(Ordering::Relaxed)
+    }
+}
+
+fn retryable_status(status: StatusCode, idempotent: bool) -> bool {
+    // Kaspi does not process throttled requests, so they are always safe to repeat
+    status == StatusCode::TOO_MANY_REQUESTS || (idempotent && status.is_server_error())
+}
+
+fn retryable_error(error: &reqwest::Error, idempotent: bool) -> bool {
+    // A request that never connected has not reached Kaspi
+    error.is_connect() || (idempotent && (error.is_timeout() || error.is_request()))
+}
+
+/// Sends the request built by `build`, retrying transient failures
+/// Non-idempotent requests are only repeated when Kaspi could not have processed them
+/// Every attempt is measured under the `endpoint` label
+pub async fn send(endpoint: &'static str, build: impl Fn() -> RequestBuilder, idempotent: bool) -> Result<Response, String> {
+    let mut attempt = 1;
+
+    loop {
+        RATE_LIMITER.acquire().await;
+
+        let started = Instant::now();
+        let response = build().send().await;
+        let failed = response.as_ref().map_or(true, |r| !r.status().is_success());
+        METRICS.observe(endpoint, started.elapsed(), failed);
+
+        let error = match response {
+            Ok(response) if response.status().is_success() => return Ok(response),
+            Ok(response) if retryable_status(response.status(), idempotent) => {
+                format!("Kaspi responded with {}", response.status())
+            }
+            Ok(response) => {
+                RETRY_STATS.failures.fetch_add(1, Ordering::Relaxed);
+                return Err(format!("Kaspi responded with {}", response.status()));
+            }
+            Err(e) if retryable_error(&e, idempotent) => e.to_string(),
+            Err(e) => {
+                RETRY_STATS.failures.fetch_add(1, Ordering::Relaxed);
+                return Err(e.to_string());
+            }
+        };
+
+        if attempt >= RETRY_POLICY.max_attempts() {
+            RETRY_STATS.failures.fetch_add(1, Ordering::Relaxed);
+            return Err(format!("Giving up after {} attempts: {}", attempt, error));
+        }
+
+        let delay = RETRY_POLICY.backoff(attempt);
+        log::warn!("Attempt {} failed ({}), retrying in {:?}", attempt, error, delay);
+        RETRY_STATS.retries.fetch_add(1, Ordering::Relaxed);
+
+        actix_rt::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_is_bounded() {
+        let policy = RetryPolicy::new(5, Duration::from_millis(100), Duration::from_secs(1));
+
+        assert_eq!(policy.ceiling(1), Duration::from_millis(100));
+        assert_eq!(policy.ceiling(3), Duration::from_millis(400));
+        assert_eq!(policy.ceiling(10), Duration::from_secs(1));
+        assert_eq!(policy.ceiling(u32::MAX), Duration::from_secs(1));
+
+        for attempt in 1..8 {
+            assert!(policy.backoff(attempt) <= policy.ceiling(attempt));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn bucket_runs_dry() {
+        let limiter = RateLimiter::new(1.0, 2.0);
+
+        assert!(limiter.try_acquire().await.is_ok());
+        assert!(limiter.try_acquire().await.is_ok());
+
+        let wait = limiter.try_acquire().await.unwrap_err();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn only_safe_requests_are_retried() {
+        assert!(retryable_status(StatusCode::TOO_MANY_REQUESTS, false));
+        assert!(!retryable_status(StatusCode::BAD_GATEWAY, false));
+        assert!(retryable_status(StatusCode::BAD_GATEWAY, true));
+        assert!(!retryable_status(StatusCode::BAD_REQUEST, true));
+    }
+}
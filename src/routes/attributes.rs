@@ -0,0 +1,29 @@
+use actix_web::{get, web, Responder, HttpResponse};
+use reqwest::Client;
+use serde::Deserialize;
+use utoipa::IntoParams;
+use crate::{DICTIONARIES, dto::ErrorBody};
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct ValuesQuery {
+    /// Code of the Kaspi category
+    category: String,
+    /// Code of the attribute
+    code: String,
+}
+
+#[utoipa::path(
+    context_path = "/attributes",
+    params(ValuesQuery),
+    responses(
+        (status = 200, description = "Values Kaspi allows for the attribute, none for free text", body = Vec<AllowedValue>),
+        (status = 502, description = "Kaspi could not be reached", body = ErrorBody)
+    )
+)]
+#[get("/values")]
+async fn values(query: web::Query<ValuesQuery>, client: web::Data<Client>) -> impl Responder {
+    match DICTIONARIES.values(&client, &query.category, &query.code).await {
+        Ok(values) => HttpResponse::Ok().json(values),
+        Err(e) => HttpResponse::BadGateway().json(ErrorBody::new(e)),
+    }
+}
@@ -1,33 +1,49 @@
-use actix_web::{get, web, Responder, HttpResponse, HttpResponseBuilder, http::StatusCode};
+use actix_web::{get, web, Responder, HttpResponse};
 use reqwest::Client;
 use futures::future;
-use serde_json::json;
 use uuid::Uuid;
 use crate::{
     STORE,
     check_code,
+    dto::{ErrorBody, ImportCheck, ImportOutcome},
+    routes::{parse_id, not_found},
 };
 
+#[utoipa::path(
+    context_path = "/code",
+    params(("id" = Uuid, Path, description = "Id of the product")),
+    responses(
+        (status = 200, description = "Status of the import, with the result once archived", body = ImportCheck),
+        (status = 400, description = "Id is not a UUID", body = ErrorBody),
+        (status = 404, description = "Product is not found", body = ErrorBody),
+        (status = 502, description = "Kaspi could not be reached", body = ErrorBody)
+    )
+)]
 #[get("/{id}")]
 async fn check(path: web::Path<String>, client: web::Data<Client>) -> impl Responder {
-    let id = path.as_str();
-
-    let result = check_code(
-        &Uuid::parse_str(id).unwrap(),
-        client.into_inner()
-    ).await;
+    let id = match parse_id(path.as_str()) {
+        Ok(id) => id,
+        Err(e) => return HttpResponse::BadRequest().json(e),
+    };
+    if STORE.get_status(&id).await.is_none() {
+        return not_found(id, "Product");
+    }
 
-    if let Ok(response_json) = result {
-        HttpResponse::Ok().json(response_json)
-    } else {
-        HttpResponse::InternalServerError().finish()
+    match check_code(&id, client.into_inner()).await {
+        Ok(import) => HttpResponse::Ok().json(import),
+        Err(e) => HttpResponse::BadGateway().json(ErrorBody::with_id(id, e)),
     }
 }
 
+#[utoipa::path(
+    context_path = "/code",
+    responses((status = 200, description = "Statuses of every import waiting for Kaspi", body = Vec<ImportOutcome>))
+)]
 #[get("/")]
 async fn check_all(client: web::Data<Client>) -> impl Responder {
-    let result: Vec<serde_json::Value> = future::join_all(
-        STORE.uploaded_ids().await.iter().map(|id| {
+    let ids = STORE.uploaded_ids().await;
+    let result: Vec<ImportOutcome> = future::join_all(
+        ids.iter().map(|id| {
             check_code(
                 id,
                 client.clone().into_inner()
@@ -35,8 +51,12 @@ async fn check_all(client: web::Data<Client>) -> impl Responder {
         }
     )).await
         .into_iter()
-        .map(|r| json!(r.unwrap()))
+        .zip(ids.iter())
+        .map(|(r, id): (Result<ImportCheck, String>, &Uuid)| match r {
+            Ok(import) => ImportOutcome::Check(import),
+            Err(e) => ImportOutcome::Error(ErrorBody::with_id(*id, e)),
+        })
         .collect();
 
-    HttpResponse::Ok().json(serde_json::to_value(result).expect("Could not create json"))
+    HttpResponse::Ok().json(result)
 }
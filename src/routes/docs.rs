@@ -0,0 +1,150 @@
+use actix_web::{get, Responder, HttpResponse, http::header};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::{Config, SwaggerUi};
+use crate::{
+    routes::{products, code, jobs, events, metrics, reconcile, attributes, templates, offers, repricing},
+    entities::{
+        product::{Product, ProductImage},
+        attribute::{Attribute, AttributeValue},
+        attempt::Attempt,
+        timestamps::Timestamps,
+        upload_result::{Status, UploadResult},
+    },
+    dto::*,
+    images::{ImageProblem, ImageReport},
+    jobs::{Job, JobState},
+    dictionary::{AllowedValue, AttributeReport, ResolvedValue, UnresolvedValue},
+    templates::{Template, TemplateItem, Overrides},
+    bulk::{Selector, Field, Operation, BulkEdit, BulkChange},
+    sanitize::DescriptionReport,
+    offers::{Source, OfferUpdate, SkuOfferUpdate, OfferChange, Offer, PendingChange},
+    repricing::{Rule, RepricingRule, CompetitorOffer, RepriceAudit, RepriceOutcome},
+    diff::{ProductDiff, FieldChange, AttributeChanges, ImageChanges},
+    reconcile::{ReconcileReport, MissingRemotely, Difference},
+    events::{EventKind, StoreEvent},
+    query::{SortKey, Order},
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "kaspi-service", description = "Bulk upload of products to the Kaspi shop"),
+    paths(
+        products::show_all,
+        products::search,
+        products::show,
+        products::history,
+        products::diff,
+        products::diff_candidate,
+        products::add,
+        products::bulk_edit,
+        products::upload_images,
+        products::remove,
+        code::check_all,
+        code::check,
+        jobs::show,
+        events::subscribe,
+        metrics::show,
+        reconcile::start,
+        reconcile::report,
+        attributes::values,
+        templates::show_all,
+        templates::save,
+        templates::remove,
+        templates::preview,
+        templates::add,
+        offers::show_all,
+        offers::pending,
+        offers::show,
+        offers::update,
+        offers::update_all,
+        offers::feed,
+        repricing::rules,
+        repricing::save_rule,
+        repricing::remove_rule,
+        repricing::run,
+        repricing::audit,
+    ),
+    components(schemas(
+        Product, ProductImage, Attribute, AttributeValue, Attempt, Timestamps, Status, UploadResult,
+        ProductSummary, ProductPage, ProductDetail, SearchHit, SearchPage, HistoryEntry, ProductHistory,
+        JobAccepted, ImagesRejected, HostedImageError, HostedImages, ImportStatus, ImportResult, ImportCheck,
+        ImportOutcome, ErrorBody, DryRun, DryRunProduct, AttributesRejected, UploadRejected,
+        ExpandError, ExpandRejected, BulkEdited, OffersUpdated,
+        ImageProblem, ImageReport, Job, JobState, EventKind, StoreEvent, SortKey, Order,
+        ReconcileReport, MissingRemotely, Difference, ProductDiff, FieldChange, AttributeChanges, ImageChanges,
+        AllowedValue, AttributeReport, ResolvedValue, UnresolvedValue,
+        Template, TemplateItem, Overrides, Selector, Field, Operation, BulkEdit, BulkChange,
+        DescriptionReport, Source, OfferUpdate, SkuOfferUpdate, OfferChange, Offer, PendingChange,
+        Rule, RepricingRule, CompetitorOffer, RepriceAudit, RepriceOutcome,
+    ))
+)]
+pub struct ApiDoc;
+
+#[get("/openapi.json")]
+async fn openapi_json() -> impl Responder {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+#[get("/docs")]
+async fn docs() -> impl Responder {
+    HttpResponse::PermanentRedirect()
+        .insert_header((header::LOCATION, "/docs/"))
+        .finish()
+}
+
+/// Swagger UI for `/openapi.json`, with its assets bundled in the binary
+pub fn swagger_ui() -> SwaggerUi {
+    SwaggerUi::new("/docs/{_:.*}").config(Config::new(["/openapi.json"]))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refs(value: &serde_json::Value, found: &mut Vec<String>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                if let Some(serde_json::Value::String(r)) = map.get("$ref") {
+                    found.push(r.trim_start_matches("#/components/schemas/").to_owned());
+                }
+                map.values().for_each(|v| refs(v, found));
+            }
+            serde_json::Value::Array(values) => values.iter().for_each(|v| refs(v, found)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn every_schema_is_defined() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let schemas = doc["components"]["schemas"].as_object().unwrap();
+
+        let mut found = Vec::new();
+        refs(&doc, &mut found);
+
+        assert!(!found.is_empty());
+        for name in found.iter() {
+            assert!(schemas.contains_key(name), "{} is not defined", name);
+        }
+        assert!(doc["paths"]["/products/{id}/history"]["get"].is_object());
+    }
+
+    #[actix_rt::test]
+    async fn bundled_swagger_ui() {
+        use actix_web::{test, App};
+
+        let app = test::init_service(App::new().service(openapi_json).service(docs).service(swagger_ui())).await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+
+        let response = test::call_service(&app, get("/docs")).await;
+        assert_eq!(response.headers().get(header::LOCATION).unwrap(), "/docs/");
+
+        let page = test::call_and_read_body(&app, get("/docs/")).await;
+        assert!(String::from_utf8_lossy(&page).contains("swagger-ui"));
+        let css = test::call_service(&app, get("/docs/swagger-ui.css")).await;
+        assert!(css.status().is_success());
+        let config = test::call_and_read_body(&app, get("/docs/swagger-initializer.js")).await;
+        assert!(String::from_utf8_lossy(&config).contains("/openapi.json"));
+    }
+}
@@ -0,0 +1,36 @@
+use actix_web::{get, web, Responder, HttpResponse};
+use futures::stream;
+use tokio::sync::broadcast::error::RecvError;
+use crate::{STORE, events::EventFilter};
+
+#[utoipa::path(
+    params(EventFilter),
+    responses((status = 200, description = "Server-Sent Events with a `StoreEvent` in every message", content_type = "text/event-stream", body = String))
+)]
+#[get("/events")]
+async fn subscribe(filter: web::Query<EventFilter>) -> impl Responder {
+    let receiver = STORE.subscribe();
+    let filter = filter.into_inner();
+
+    let events = stream::unfold((receiver, filter), |(mut receiver, filter)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) if filter.matches(&event) => {
+                    let message = web::Bytes::from(event.to_sse());
+                    return Some((Ok::<_, actix_web::Error>(message), (receiver, filter)));
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("Event stream lagged, {} events skipped", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events)
+}
@@ -0,0 +1,25 @@
+use actix_web::{get, web, Responder, HttpResponse};
+use crate::{JOBS, routes::{parse_id, not_found}};
+
+#[utoipa::path(
+    context_path = "/jobs",
+    params(("id" = Uuid, Path, description = "Id of the upload job")),
+    responses(
+        (status = 200, description = "Progress of the job", body = Job),
+        (status = 400, description = "Id is not a UUID", body = ErrorBody),
+        (status = 404, description = "Job is not found", body = ErrorBody)
+    )
+)]
+#[get("/{id}")]
+async fn show(path: web::Path<String>) -> impl Responder {
+    let id = match parse_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return HttpResponse::BadRequest().json(e),
+    };
+
+    if let Some(job) = JOBS.get(&id).await {
+        HttpResponse::Ok().json(job)
+    } else {
+        not_found(id, "Job")
+    }
+}
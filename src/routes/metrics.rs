@@ -0,0 +1,12 @@
+use actix_web::{get, Responder, HttpResponse};
+use crate::METRICS;
+
+#[utoipa::path(
+    responses((status = 200, description = "Metrics in the Prometheus text format", content_type = "text/plain", body = String))
+)]
+#[get("/metrics")]
+async fn show() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(METRICS.render().await)
+}
@@ -1,2 +1,36 @@
+use actix_web::{HttpResponse, HttpRequest, error::{self, JsonPayloadError, QueryPayloadError}};
+use uuid::Uuid;
+use crate::dto::ErrorBody;
+
 pub mod products;
 pub mod code;
+pub mod jobs;
+pub mod events;
+pub mod metrics;
+pub mod docs;
+pub mod reconcile;
+pub mod attributes;
+pub mod templates;
+pub mod offers;
+pub mod repricing;
+
+/// Parses the id taken from the path
+pub(crate) fn parse_id(id: &str) -> Result<Uuid, ErrorBody> {
+    Uuid::parse_str(id).map_err(|e| ErrorBody::new(format!("Invalid id '{}': {}", id, e)))
+}
+
+pub(crate) fn not_found(id: Uuid, what: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(ErrorBody::with_id(id, format!("{} is not found", what)))
+}
+
+/// Answers malformed JSON bodies with an `ErrorBody`
+pub fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> error::Error {
+    let response = HttpResponse::BadRequest().json(ErrorBody::new(&err));
+    error::InternalError::from_response(err, response).into()
+}
+
+/// Answers malformed query strings with an `ErrorBody`
+pub fn query_error(err: QueryPayloadError, _req: &HttpRequest) -> error::Error {
+    let response = HttpResponse::BadRequest().json(ErrorBody::new(&err));
+    error::InternalError::from_response(err, response).into()
+}
@@ -0,0 +1,116 @@
+use actix_web::{get, put, post, web, Responder, HttpResponse};
+use chrono::Utc;
+use crate::{
+    STORE,
+    OFFERS,
+    offers::{self, Merchant, OfferUpdate, SkuOfferUpdate, Source},
+    dto::{ErrorBody, OffersUpdated},
+};
+
+fn check(updates: &[SkuOfferUpdate]) -> Result<(), ErrorBody> {
+    match updates.iter().find(|u| u.offer.price == 0 || u.sku.trim().is_empty()) {
+        Some(u) if u.sku.trim().is_empty() => Err(ErrorBody::new("Offer has no SKU")),
+        Some(u) => Err(ErrorBody::new(format!("Price of {} is zero", u.sku))),
+        None => Ok(()),
+    }
+}
+
+#[utoipa::path(
+    context_path = "/offers",
+    responses((status = 200, description = "Every offer, by SKU", body = Vec<Offer>))
+)]
+#[get("/")]
+async fn show_all() -> impl Responder {
+    HttpResponse::Ok().json(OFFERS.all().await)
+}
+
+#[utoipa::path(
+    context_path = "/offers",
+    responses((status = 200, description = "Offers changed since the last feed, by SKU", body = Vec<PendingChange>))
+)]
+#[get("/pending")]
+async fn pending() -> impl Responder {
+    HttpResponse::Ok().json(OFFERS.pending().await)
+}
+
+#[utoipa::path(
+    context_path = "/offers",
+    params(("sku" = String, Path, description = "SKU of the product")),
+    responses(
+        (status = 200, description = "Offer with its history", body = Offer),
+        (status = 404, description = "Offer is not found", body = ErrorBody)
+    )
+)]
+#[get("/{sku}")]
+async fn show(path: web::Path<String>) -> impl Responder {
+    match OFFERS.get(&path).await {
+        Some(offer) => HttpResponse::Ok().json(offer),
+        None => HttpResponse::NotFound().json(ErrorBody::new(format!("Offer {} is not found", path))),
+    }
+}
+
+#[utoipa::path(
+    context_path = "/offers",
+    params(("sku" = String, Path, description = "SKU of the product")),
+    request_body = OfferUpdate,
+    responses(
+        (status = 200, description = "Offer is stored", body = Offer),
+        (status = 400, description = "Price is zero", body = ErrorBody)
+    )
+)]
+#[put("/{sku}")]
+async fn update(path: web::Path<String>, offer: web::Json<OfferUpdate>) -> impl Responder {
+    let sku = path.into_inner();
+    let updates = vec![SkuOfferUpdate { sku: sku.clone(), offer: offer.into_inner() }];
+    if let Err(e) = check(&updates) {
+        return HttpResponse::BadRequest().json(e);
+    }
+
+    OFFERS.update(updates, Source::Api).await;
+
+    HttpResponse::Ok().json(OFFERS.get(&sku).await)
+}
+
+#[utoipa::path(
+    context_path = "/offers",
+    request_body = Vec<SkuOfferUpdate>,
+    responses(
+        (status = 200, description = "Offers are stored", body = OffersUpdated),
+        (status = 400, description = "An offer has no SKU or a zero price", body = ErrorBody)
+    )
+)]
+#[put("/")]
+async fn update_all(updates: web::Json<Vec<SkuOfferUpdate>>) -> impl Responder {
+    let updates = updates.into_inner();
+    if let Err(e) = check(&updates) {
+        return HttpResponse::BadRequest().json(e);
+    }
+
+    let (changed, unchanged): (Vec<_>, Vec<_>) = OFFERS.update(updates, Source::Api)
+        .await
+        .into_iter()
+        .partition(|(_, changed)| *changed);
+
+    HttpResponse::Ok().json(OffersUpdated {
+        changed: changed.into_iter().map(|(sku, _)| sku).collect(),
+        unchanged: unchanged.into_iter().map(|(sku, _)| sku).collect(),
+    })
+}
+
+#[utoipa::path(
+    context_path = "/offers",
+    responses((status = 200, description = "Price list for Kaspi, every offer is marked as published", content_type = "application/xml", body = String))
+)]
+#[post("/feed")]
+async fn feed() -> impl Responder {
+    let products = STORE.latest_versions()
+        .await
+        .into_iter()
+        .map(|(_, product)| (product.sku().to_owned(), product))
+        .collect();
+    let published = OFFERS.publish().await;
+
+    HttpResponse::Ok()
+        .content_type("application/xml; charset=utf-8")
+        .body(offers::feed(&Merchant::from_env(), &published, &products, Utc::now()))
+}
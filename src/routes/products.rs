@@ -1,92 +1,540 @@
-use actix_web::{get, post, web, HttpResponse, Responder, HttpResponseBuilder, http::StatusCode, delete};
+use actix_web::{get, post, web, HttpResponse, Responder, delete};
 use reqwest::Client;
-use serde_json::{json, Value};
-use futures::future;
+use std::collections::HashMap;
+use actix_multipart::Multipart;
+use futures::StreamExt;
+use chrono::Utc;
+use uuid::Uuid;
+use serde::Deserialize;
+use utoipa::IntoParams;
 use crate::{
     STORE,
-    send_to_kaspi,
+    JOBS,
+    IMAGE_PROBER,
+    IMAGE_HOST,
+    DICTIONARIES,
+    DRY_RUN,
+    import_body,
+    unchanged_version,
+    diff::ProductDiff,
+    bulk::{self, BulkEdit, BulkChange},
     entities::{product::Product, upload_result::Status},
+    images::{self, ImageReport},
+    dictionary::AttributeReport,
+    sanitize::{self, DescriptionReport},
+    query::{Entry, ListQuery, DEFAULT_LIMIT, MAX_LIMIT},
+    dto::{
+        ProductSummary, ProductPage, ProductDetail, SearchHit, SearchPage,
+        HistoryEntry, ProductHistory, JobAccepted, ImagesRejected, HostedImageError, HostedImages,
+        ErrorBody, DryRun, DryRunProduct, AttributesRejected, UploadRejected, BulkEdited,
+    },
+    routes::{parse_id, not_found},
 };
 
+#[utoipa::path(
+    context_path = "/products",
+    params(ListQuery),
+    responses((status = 200, description = "Page of the products matching the filters", body = ProductPage))
+)]
 #[get("/")]
-async fn show_all() -> impl Responder {
-    let mut json: Vec<Value> = Vec::new();
+async fn show_all(query: web::Query<ListQuery>) -> impl Responder {
+    let now = Utc::now();
 
-    for (id, product) in STORE.products().await.iter() {
-        let (code, status) = STORE.get_status(id).await.unwrap();
+    let mut results = HashMap::new();
+    let mut records = Vec::new();
 
-        let entry = json!({
-            "id": id,
-            "sku": product.sku(),
-            "code": code,
-            "status": status
-        });
+    for (id, product, code, status) in STORE.uploaded_products().await {
+        let timestamps = STORE.get_timestamps(&id).await.unwrap_or_default();
+        if let Some(result) = STORE.get_result(&id).await {
+            results.insert(id, result);
+        }
+
+        records.push((id, product, code, status, timestamps));
+    }
+
+    let mut entries: Vec<(Entry, ProductSummary)> = records
+        .iter()
+        .map(|(id, product, code, status, timestamps)| {
+            let entry = Entry { product, status: *status, timestamps: *timestamps, result: results.get(id) };
+            let summary = ProductSummary {
+                id: *id,
+                sku: product.sku().to_owned(),
+                code: code.clone(),
+                status: *status,
+                timestamps: *timestamps,
+            };
+
+            (entry, summary)
+        })
+        .filter(|(entry, _)| query.matches(entry, now))
+        .collect();
+
+    query.sort(&mut entries);
+
+    let total = entries.len();
+    let items: Vec<ProductSummary> = entries
+        .drain(query.range(total))
+        .map(|(_, summary)| summary)
+        .collect();
 
-        json.push(entry);
+    HttpResponse::Ok().json(ProductPage {
+        total,
+        page: query.page(),
+        limit: query.limit(),
+        items,
+    })
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct SearchQuery {
+    /// Words to look for in the title, description, brand and attribute values
+    q: String,
+    limit: Option<usize>,
+}
+
+#[utoipa::path(
+    context_path = "/products",
+    params(SearchQuery),
+    responses((status = 200, description = "Products matching every word, best first", body = SearchPage))
+)]
+#[get("/search")]
+async fn search(query: web::Query<SearchQuery>) -> impl Responder {
+    let found = STORE.search(&query.q).await;
+    let total = found.len();
+
+    let mut items: Vec<SearchHit> = Vec::new();
+    for (id, score) in found.into_iter().take(query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)) {
+        if let Some(product) = STORE.get_product(&id).await {
+            items.push(SearchHit {
+                id,
+                sku: product.sku().to_owned(),
+                title: product.title().to_owned(),
+                score,
+            });
+        }
     }
 
-    HttpResponse::Ok().json(json)
+    HttpResponse::Ok().json(SearchPage { total, items })
 }
 
+#[utoipa::path(
+    context_path = "/products",
+    params(("id" = Uuid, Path, description = "Id of the product")),
+    responses(
+        (status = 200, description = "Product with its upload state", body = ProductDetail),
+        (status = 400, description = "Id is not a UUID", body = ErrorBody),
+        (status = 404, description = "Product is not found", body = ErrorBody)
+    )
+)]
 #[get("/{id}")]
 async fn show(path: web::Path<String>) -> impl Responder {
-    let id = uuid::Uuid::parse_str(&path.into_inner()).unwrap();
+    let id = match parse_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return HttpResponse::BadRequest().json(e),
+    };
 
-    if let Some((code, status)) = STORE.get_status(&id).await {
-        let product = STORE.get_product(&id).await.unwrap();
+    if let (Some((code, status)), Some(product)) = (STORE.get_status(&id).await, STORE.get_product(&id).await) {
         let result = STORE.get_result(&id).await;
+        let timestamps = STORE.get_timestamps(&id).await.unwrap_or_default();
 
-        let json = json!({
-            "id": id,
-            "code": code,
-            "status": status,
-            "product": product,
-            "result": result
-        });
+        HttpResponse::Ok().json(ProductDetail { id, code, status, product, result, timestamps })
+    } else {
+        not_found(id, "Product")
+    }
+}
+
+#[utoipa::path(
+    context_path = "/products",
+    params(("id" = Uuid, Path, description = "Id of any version of the product")),
+    responses(
+        (status = 200, description = "Attempts of every version of the product, oldest first", body = ProductHistory),
+        (status = 400, description = "Id is not a UUID", body = ErrorBody),
+        (status = 404, description = "Product is not found", body = ErrorBody)
+    )
+)]
+#[get("/{id}/history")]
+async fn history(path: web::Path<String>) -> impl Responder {
+    let id = match parse_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return HttpResponse::BadRequest().json(e),
+    };
+
+    if let Some(product) = STORE.get_product(&id).await {
+        // Every version of the product shares the SKU
+        let versions: Vec<Uuid> = STORE.products().await
+            .iter()
+            .filter(|(_, p)| p.sku() == product.sku())
+            .map(|(id, _)| *id)
+            .collect();
+
+        let mut attempts = Vec::new();
+        for version in versions.into_iter() {
+            for attempt in STORE.get_history(&version).await.into_iter() {
+                attempts.push((version, attempt));
+            }
+        }
+        attempts.sort_by_key(|(_, attempt)| attempt.at);
+
+        let attempts: Vec<HistoryEntry> = attempts
+            .into_iter()
+            .map(|(id, attempt)| HistoryEntry { id, attempt })
+            .collect();
 
-        HttpResponse::Ok().json(json)
+        HttpResponse::Ok().json(ProductHistory {
+            id,
+            sku: product.sku().to_owned(),
+            attempts,
+        })
     } else {
-        HttpResponseBuilder::new(StatusCode::INTERNAL_SERVER_ERROR).finish()
+        not_found(id, "Product")
+    }
+}
+
+#[utoipa::path(
+    context_path = "/products",
+    params(
+        ("id" = Uuid, Path, description = "Id of the old version"),
+        ("other" = Uuid, Path, description = "Id of the new version")
+    ),
+    responses(
+        (status = 200, description = "Changes from the old version to the new one", body = ProductDiff),
+        (status = 400, description = "Id is not a UUID", body = ErrorBody),
+        (status = 404, description = "Product is not found", body = ErrorBody)
+    )
+)]
+#[get("/{id}/diff/{other}")]
+async fn diff(path: web::Path<(String, String)>) -> impl Responder {
+    let (id, other) = path.into_inner();
+    let (id, other) = match (parse_id(&id), parse_id(&other)) {
+        (Ok(id), Ok(other)) => (id, other),
+        (Err(e), _) | (_, Err(e)) => return HttpResponse::BadRequest().json(e),
+    };
+
+    match (STORE.get_product(&id).await, STORE.get_product(&other).await) {
+        (Some(old), Some(new)) => HttpResponse::Ok().json(ProductDiff::between(&old, &new)),
+        (None, _) => not_found(id, "Product"),
+        (_, None) => not_found(other, "Product"),
     }
 }
 
+#[utoipa::path(
+    context_path = "/products",
+    params(("id" = Uuid, Path, description = "Id of the stored version")),
+    request_body = Product,
+    responses(
+        (status = 200, description = "Changes the candidate would make to the stored version", body = ProductDiff),
+        (status = 400, description = "Id is not a UUID or the body is not a product", body = ErrorBody),
+        (status = 404, description = "Product is not found", body = ErrorBody)
+    )
+)]
+#[post("/{id}/diff")]
+async fn diff_candidate(path: web::Path<String>, candidate: web::Json<Product>) -> impl Responder {
+    let id = match parse_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return HttpResponse::BadRequest().json(e),
+    };
+
+    if let Some(product) = STORE.get_product(&id).await {
+        HttpResponse::Ok().json(ProductDiff::between(&product, &candidate))
+    } else {
+        not_found(id, "Product")
+    }
+}
+
+#[derive(Deserialize, IntoParams, Debug, Default)]
+pub struct AddQuery {
+    /// Download every image to check it before uploading
+    #[serde(default)]
+    probe_images: bool,
+    /// Replace free-text attribute values with the ones Kaspi allows
+    #[serde(default)]
+    normalize_attributes: bool,
+    /// Answer with what would be sent to Kaspi, without sending or storing anything
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[utoipa::path(
+    context_path = "/products",
+    params(AddQuery),
+    request_body = Vec<Product>,
+    responses(
+        (status = 202, description = "Products are queued for upload", body = JobAccepted),
+        (status = 200, description = "Dry run, nothing is sent or stored", body = DryRun),
+        (status = 400, description = "Body is not a list of products", body = ErrorBody),
+        (status = 422, description = "Some images or attribute values can not be accepted by Kaspi", body = UploadRejected)
+    )
+)]
 #[post("/")]
-async fn add(products: web::Json<Vec<Product>>, client: web::Data<Client>) -> impl Responder {
-    let results = future::join_all(
-        products.into_inner().iter().map(|product| {
-            let product_json = serde_json::to_value(
-                product
-                .clone()
-            ).expect("Could not convert to json");
-
-            send_to_kaspi(
-                product_json, client.clone().into_inner()
-            )
+async fn add(products: web::Json<Vec<Product>>, query: web::Query<AddQuery>, client: web::Data<Client>) -> impl Responder {
+    submit(products.into_inner(), &query, &client).await.respond()
+}
+
+/// What became of the submitted products
+pub(crate) enum Submission {
+    Queued(JobAccepted),
+    DryRun(DryRun),
+    Rejected(UploadRejected),
+}
+
+impl Submission {
+    pub(crate) fn respond(self) -> HttpResponse {
+        match self {
+            Submission::Queued(accepted) => HttpResponse::Accepted().json(accepted),
+            Submission::DryRun(dry_run) => HttpResponse::Ok().json(dry_run),
+            Submission::Rejected(rejected) => HttpResponse::UnprocessableEntity().json(rejected),
+        }
+    }
+}
+
+/// Checks the products and queues them for upload
+pub(crate) async fn submit(mut products: Vec<Product>, query: &AddQuery, client: &Client) -> Submission {
+    let mut reports: Vec<ImageReport> = Vec::new();
+
+    for product in products.iter_mut() {
+        let mut report = images::check_urls(product);
+        if query.probe_images {
+            IMAGE_PROBER.check(product, &mut report).await;
         }
-    )).await;
 
-    let mut duplicates: Vec<String> = Vec::new();
-    let mut codes: Vec<String> = Vec::new();
-    for result in results.into_iter() {
-        let _ = result.as_ref()
-            .map_err(|s| duplicates.push(s.to_owned()))
-            .map(|s| codes.push(s.to_owned()));
+        if !report.is_clean() {
+            reports.push(report);
+        }
     }
 
-    if duplicates.is_empty() {
-        let json = serde_json::to_value(codes).expect("Could not create Value");
-        HttpResponse::Ok().json(json)
-    } else {
-        let json = serde_json::to_value(duplicates).expect("Could not create Value");
-        HttpResponse::Found().json(json)
+    if reports.iter().any(|r| !r.is_ok()) {
+        return Submission::Rejected(UploadRejected::Images(ImagesRejected { images: reports }));
+    }
+
+    let descriptions: Vec<DescriptionReport> = products
+        .iter_mut()
+        .map(sanitize::sanitize_product)
+        .filter(|report| !report.is_clean())
+        .collect();
+
+    let mut attributes: Vec<AttributeReport> = Vec::new();
+    if query.normalize_attributes {
+        for product in products.iter_mut() {
+            let report = DICTIONARIES.normalize(client, product).await;
+            if !report.is_clean() {
+                attributes.push(report);
+            }
+        }
+    }
+
+    if attributes.iter().any(|r| !r.is_ok()) {
+        return Submission::Rejected(UploadRejected::Attributes(AttributesRejected { attributes }));
     }
+
+    if query.dry_run || *DRY_RUN {
+        return Submission::DryRun(dry_run(products, reports, attributes, descriptions).await);
+    }
+
+    let id = JOBS.enqueue(products).await;
+
+    Submission::Queued(JobAccepted { id, images: reports, attributes, descriptions })
 }
 
+/// Derives what `send_to_kaspi` would send for the products
+async fn dry_run(
+    products: Vec<Product>,
+    images: Vec<ImageReport>,
+    attributes: Vec<AttributeReport>,
+    descriptions: Vec<DescriptionReport>,
+) -> DryRun {
+    let mut seen = std::collections::HashSet::new();
+    let mut planned = Vec::new();
+
+    for product in products {
+        let (id, _, body) = import_body(serde_json::to_value(&product).expect("Could not convert to json"));
+
+        let mut warnings = Vec::new();
+        if !seen.insert(id) {
+            warnings.push(String::from("Same product is earlier in the request"));
+        }
+        match STORE.get_status(&id).await {
+            Some((_, Status::ABORTED)) => warnings.push(String::from("Aborted upload would be retried")),
+            Some((code, _)) => warnings.push(format!("Duplicate product {}, uploaded as {}", product.sku(), code)),
+            None if STORE.get_product(&id).await.is_some() => {
+                warnings.push(format!("Duplicate product {}, being uploaded", product.sku()))
+            }
+            None => {}
+        }
+        if let Some(version) = unchanged_version(&id, &product).await {
+            warnings.push(format!("Unchanged product, same as {}", version));
+        }
+
+        planned.push(DryRunProduct { id, sku: product.sku().to_owned(), body, warnings });
+    }
+
+    DryRun { products: planned, images, attributes, descriptions }
+}
+
+#[derive(Deserialize, IntoParams, Debug, Default)]
+pub struct BulkQuery {
+    /// Only list the products the edit would change
+    #[serde(default)]
+    preview: bool,
+}
+
+#[utoipa::path(
+    context_path = "/products",
+    params(BulkQuery),
+    request_body = BulkEdit,
+    responses(
+        (status = 202, description = "Edited products are queued for upload as new versions", body = BulkEdited),
+        (status = 200, description = "Preview of the products the edit changes, or a dry run", body = BulkEdited),
+        (status = 400, description = "Selector matches every product", body = ErrorBody),
+        (status = 422, description = "Some edited products can not be accepted by Kaspi", body = UploadRejected)
+    )
+)]
+#[post("/bulk")]
+async fn bulk_edit(edit: web::Json<BulkEdit>, query: web::Query<BulkQuery>, client: web::Data<Client>) -> impl Responder {
+    if edit.selector.is_empty() {
+        return HttpResponse::BadRequest().json(ErrorBody::new("Selector has no criteria"));
+    }
+
+    let mut edited: Vec<(Uuid, Product, ProductDiff)> = Vec::new();
+    for (id, product) in STORE.latest_versions().await.into_iter() {
+        let Some((_, status)) = STORE.get_status(&id).await else {
+            continue;
+        };
+        if !edit.selector.matches(&product, status) {
+            continue;
+        }
+
+        let new = bulk::apply(&product, &edit.operations);
+        let changes = ProductDiff::between(&product, &new);
+        if !changes.is_empty() {
+            edited.push((id, new, changes));
+        }
+    }
+    edited.sort_by(|(_, a, _), (_, b, _)| a.sku().cmp(b.sku()));
+
+    let products: Vec<BulkChange> = edited
+        .iter()
+        .map(|(id, product, changes)| BulkChange { id: *id, sku: product.sku().to_owned(), diff: changes.clone() })
+        .collect();
+
+    if query.preview || edited.is_empty() {
+        return HttpResponse::Ok().json(BulkEdited { products, applied: false, job: None });
+    }
+
+    // Goes through the same checks, dry run included, as any other upload
+    let edited: Vec<Product> = edited.into_iter().map(|(_, product, _)| product).collect();
+    match submit(edited, &AddQuery::default(), &client).await {
+        Submission::Queued(accepted) => {
+            HttpResponse::Accepted().json(BulkEdited { products, applied: true, job: Some(accepted.id) })
+        }
+        Submission::DryRun(_) => HttpResponse::Ok().json(BulkEdited { products, applied: false, job: None }),
+        rejected @ Submission::Rejected(_) => rejected.respond(),
+    }
+}
+
+/// Largest accepted file, before resizing
+pub const MAX_UPLOAD_BYTES: usize = 32 * 1024 * 1024;
+
+#[utoipa::path(
+    context_path = "/products",
+    params(("id" = Uuid, Path, description = "Id of the product")),
+    request_body(content = String, content_type = "multipart/form-data", description = "Image files"),
+    responses(
+        (status = 200, description = "New version of the product with the hosted images is queued for upload", body = HostedImages),
+        (status = 400, description = "Id is not a UUID or the form is malformed", body = ErrorBody),
+        (status = 404, description = "Product is not found", body = ErrorBody),
+        (status = 413, description = "A file is too large", body = ErrorBody),
+        (status = 422, description = "New version can not be accepted by Kaspi", body = UploadRejected),
+        (status = 503, description = "Image hosting is disabled, IMAGES_BASE_URL is not set", body = ErrorBody)
+    )
+)]
+#[post("/{id}/images")]
+async fn upload_images(path: web::Path<String>, mut payload: Multipart, client: web::Data<Client>) -> impl Responder {
+    if IMAGE_HOST.base_url().is_none() {
+        return HttpResponse::ServiceUnavailable()
+            .json(ErrorBody::new("Image hosting is disabled, IMAGES_BASE_URL is not set"));
+    }
+
+    let id = match parse_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return HttpResponse::BadRequest().json(e),
+    };
+
+    let Some(mut product) = STORE.get_product(&id).await else {
+        return not_found(id, "Product");
+    };
+
+    let mut urls: Vec<String> = Vec::new();
+    let mut errors: Vec<HostedImageError> = Vec::new();
+
+    while let Some(field) = payload.next().await {
+        let mut field = match field {
+            Ok(field) => field,
+            Err(e) => return HttpResponse::BadRequest().json(ErrorBody::with_id(id, e)),
+        };
+        let name = field.content_disposition()
+            .and_then(|d| d.get_filename())
+            .unwrap_or_default()
+            .to_owned();
+
+        let mut bytes: Vec<u8> = Vec::new();
+        while let Some(chunk) = field.next().await {
+            match chunk {
+                Ok(chunk) if bytes.len() + chunk.len() <= MAX_UPLOAD_BYTES => bytes.extend_from_slice(&chunk),
+                Ok(_) => {
+                    let error = format!("'{}' is larger than {} bytes", name, MAX_UPLOAD_BYTES);
+                    return HttpResponse::PayloadTooLarge().json(ErrorBody::with_id(id, error));
+                }
+                Err(e) => return HttpResponse::BadRequest().json(ErrorBody::with_id(id, e)),
+            }
+        }
+
+        match IMAGE_HOST.save(bytes).await {
+            Ok(url) => {
+                product.add_image(url.clone());
+                urls.push(url);
+            }
+            Err(error) => errors.push(HostedImageError { file: name, error }),
+        }
+    }
+
+    if urls.is_empty() {
+        return HttpResponse::Ok().json(HostedImages { id, urls, errors, product, job: None });
+    }
+
+    // The stored version keeps the contents Kaspi received, the images go out as a new one
+    let job = match submit(vec![product.clone()], &AddQuery::default(), &client).await {
+        Submission::Queued(accepted) => Some(accepted.id),
+        Submission::DryRun(_) => None,
+        rejected @ Submission::Rejected(_) => return rejected.respond(),
+    };
+
+    HttpResponse::Ok().json(HostedImages { id, urls, errors, product, job })
+}
+
+#[utoipa::path(
+    context_path = "/products",
+    params(("id" = Uuid, Path, description = "Id of the product")),
+    responses(
+        (status = 204, description = "Product is removed"),
+        (status = 400, description = "Id is not a UUID", body = ErrorBody),
+        (status = 404, description = "Product is not found", body = ErrorBody),
+        (status = 409, description = "Product is already uploaded to Kaspi", body = ErrorBody)
+    )
+)]
 #[delete("/{id}")]
-async fn remove(path: web::Path<String>) -> impl Responder {
-    let _id = path.into_inner();
+async fn remove(path: web::Path<String>) -> HttpResponse {
+    let id = match parse_id(&path.into_inner()) {
+        Ok(id) => id,
+        Err(e) => return HttpResponse::BadRequest().json(e),
+    };
 
-    todo!();
+    // Uploaded products stay, Kaspi keeps them anyway
+    if STORE.get_status(&id).await.is_some() {
+        return HttpResponse::Conflict().json(ErrorBody::with_id(id, "Product is already uploaded to Kaspi"));
+    }
 
-    HttpResponse::Ok().finish()
+    match STORE.remove_product(&id).await {
+        Some(_) => HttpResponse::NoContent().finish(),
+        None => not_found(id, "Product"),
+    }
 }
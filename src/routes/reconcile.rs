@@ -0,0 +1,40 @@
+use actix_web::{get, post, web, Responder, HttpResponse};
+use reqwest::Client;
+use crate::{
+    RECONCILER,
+    reconcile::LastRun,
+    dto::ErrorBody,
+};
+
+#[utoipa::path(
+    context_path = "/reconcile",
+    responses(
+        (status = 202, description = "Reconciliation is started"),
+        (status = 409, description = "A reconciliation is already running", body = ErrorBody)
+    )
+)]
+#[post("/")]
+async fn start(client: web::Data<Client>) -> impl Responder {
+    if RECONCILER.start(client.into_inner()) {
+        HttpResponse::Accepted().finish()
+    } else {
+        HttpResponse::Conflict().json(ErrorBody::new("Reconciliation is already running"))
+    }
+}
+
+#[utoipa::path(
+    context_path = "/reconcile",
+    responses(
+        (status = 200, description = "Report of the last reconciliation", body = ReconcileReport),
+        (status = 404, description = "No reconciliation has finished", body = ErrorBody),
+        (status = 502, description = "The last reconciliation could not fetch the catalog", body = ErrorBody)
+    )
+)]
+#[get("/")]
+async fn report() -> impl Responder {
+    match RECONCILER.last().await {
+        Some(LastRun::Done(report)) => HttpResponse::Ok().json(report),
+        Some(LastRun::Failed(e)) => HttpResponse::BadGateway().json(ErrorBody::new(e)),
+        None => HttpResponse::NotFound().json(ErrorBody::new("No reconciliation has finished")),
+    }
+}
@@ -0,0 +1,96 @@
+use actix_web::{get, put, post, delete, web, Responder, HttpResponse};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use crate::{
+    OFFERS,
+    REPRICER,
+    offers::Merchant,
+    repricing::{RepricingRule, Rule},
+    query::{DEFAULT_LIMIT, MAX_LIMIT},
+    dto::ErrorBody,
+};
+
+#[utoipa::path(
+    context_path = "/repricing",
+    responses((status = 200, description = "Every repricing rule, by SKU", body = Vec<RepricingRule>))
+)]
+#[get("/rules")]
+async fn rules() -> impl Responder {
+    HttpResponse::Ok().json(REPRICER.rules().await)
+}
+
+#[utoipa::path(
+    context_path = "/repricing",
+    params(("sku" = String, Path, description = "SKU of the offer")),
+    request_body = RepricingRule,
+    responses(
+        (status = 200, description = "Rule is stored", body = RepricingRule),
+        (status = 400, description = "Bounds are empty or the position is 0", body = ErrorBody)
+    )
+)]
+#[put("/rules/{sku}")]
+async fn save_rule(path: web::Path<String>, rule: web::Json<RepricingRule>) -> impl Responder {
+    let rule = RepricingRule { sku: path.into_inner(), ..rule.into_inner() };
+    if rule.min_price == 0 || rule.min_price > rule.max_price {
+        return HttpResponse::BadRequest().json(ErrorBody::new("Prices must satisfy 0 < min_price <= max_price"));
+    }
+    if rule.rule == (Rule::Position { position: 0 }) {
+        return HttpResponse::BadRequest().json(ErrorBody::new("Positions start from 1"));
+    }
+
+    REPRICER.insert_rule(rule.clone()).await;
+    HttpResponse::Ok().json(rule)
+}
+
+#[utoipa::path(
+    context_path = "/repricing",
+    params(("sku" = String, Path, description = "SKU of the offer")),
+    responses(
+        (status = 204, description = "Rule is removed"),
+        (status = 404, description = "Rule is not found", body = ErrorBody)
+    )
+)]
+#[delete("/rules/{sku}")]
+async fn remove_rule(path: web::Path<String>) -> impl Responder {
+    if REPRICER.remove_rule(&path).await.is_some() {
+        HttpResponse::NoContent().finish()
+    } else {
+        HttpResponse::NotFound().json(ErrorBody::new(format!("Rule {} is not found", path)))
+    }
+}
+
+#[utoipa::path(
+    context_path = "/repricing",
+    responses(
+        (status = 200, description = "New prices of the offers with rules", body = Vec<RepriceOutcome>),
+        (status = 500, description = "KASPI_MERCHANT_ID is not set", body = ErrorBody),
+        (status = 502, description = "Competitor offers could not be read", body = ErrorBody)
+    )
+)]
+#[post("/run")]
+async fn run() -> impl Responder {
+    let merchant_id = Merchant::from_env().merchant_id;
+    if merchant_id.trim().is_empty() {
+        return HttpResponse::InternalServerError().json(ErrorBody::new("KASPI_MERCHANT_ID is required for repricing"));
+    }
+
+    match REPRICER.run(&OFFERS, &merchant_id).await {
+        Ok(outcomes) => HttpResponse::Ok().json(outcomes),
+        Err(e) => HttpResponse::BadGateway().json(ErrorBody::new(e)),
+    }
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+pub struct AuditQuery {
+    limit: Option<usize>,
+}
+
+#[utoipa::path(
+    context_path = "/repricing",
+    params(AuditQuery),
+    responses((status = 200, description = "Price changes made by the repricer, newest first", body = Vec<RepriceAudit>))
+)]
+#[get("/audit")]
+async fn audit(query: web::Query<AuditQuery>) -> impl Responder {
+    HttpResponse::Ok().json(REPRICER.audit(query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)).await)
+}
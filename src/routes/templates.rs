@@ -0,0 +1,117 @@
+use actix_web::{get, put, post, delete, web, Responder, HttpResponse};
+use reqwest::Client;
+use crate::{
+    TEMPLATES,
+    entities::product::Product,
+    templates::{Template, TemplateItem},
+    dto::{ErrorBody, ExpandError, ExpandRejected},
+    routes::products::{submit, AddQuery},
+};
+
+fn template_not_found(name: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(ErrorBody::new(format!("Template {} is not found", name)))
+}
+
+/// Builds the products of the items, or answers with the ones that could not be built
+async fn expand(name: &str, items: &[TemplateItem]) -> Result<Vec<Product>, HttpResponse> {
+    let Some(template) = TEMPLATES.get(name).await else {
+        return Err(template_not_found(name));
+    };
+
+    let mut products = Vec::new();
+    let mut errors = Vec::new();
+    for item in items.iter() {
+        match template.expand(item) {
+            Ok(product) => products.push(product),
+            Err(error) => errors.push(ExpandError { sku: item.sku.clone(), error }),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(products)
+    } else {
+        Err(HttpResponse::UnprocessableEntity().json(ExpandRejected { items: errors }))
+    }
+}
+
+#[utoipa::path(
+    context_path = "/templates",
+    responses((status = 200, description = "Every template, by name", body = Vec<Template>))
+)]
+#[get("/")]
+async fn show_all() -> impl Responder {
+    HttpResponse::Ok().json(TEMPLATES.all().await)
+}
+
+#[utoipa::path(
+    context_path = "/templates",
+    params(("name" = String, Path, description = "Name of the template")),
+    request_body = Template,
+    responses((status = 200, description = "Template is stored", body = Template))
+)]
+#[put("/{name}")]
+async fn save(path: web::Path<String>, template: web::Json<Template>) -> impl Responder {
+    let template = Template { name: path.into_inner(), ..template.into_inner() };
+    TEMPLATES.insert(template.clone()).await;
+
+    HttpResponse::Ok().json(template)
+}
+
+#[utoipa::path(
+    context_path = "/templates",
+    params(("name" = String, Path, description = "Name of the template")),
+    responses(
+        (status = 204, description = "Template is removed"),
+        (status = 404, description = "Template is not found", body = ErrorBody)
+    )
+)]
+#[delete("/{name}")]
+async fn remove(path: web::Path<String>) -> impl Responder {
+    if TEMPLATES.remove(&path).await.is_some() {
+        HttpResponse::NoContent().finish()
+    } else {
+        template_not_found(&path)
+    }
+}
+
+#[utoipa::path(
+    context_path = "/templates",
+    params(("name" = String, Path, description = "Name of the template")),
+    request_body = Vec<TemplateItem>,
+    responses(
+        (status = 200, description = "Products built from the items, nothing is uploaded", body = Vec<Product>),
+        (status = 404, description = "Template is not found", body = ErrorBody),
+        (status = 422, description = "Some items could not be built", body = ExpandRejected)
+    )
+)]
+#[post("/{name}/expand")]
+async fn preview(path: web::Path<String>, items: web::Json<Vec<TemplateItem>>) -> impl Responder {
+    match expand(&path, &items).await {
+        Ok(products) => HttpResponse::Ok().json(products),
+        Err(response) => response,
+    }
+}
+
+#[utoipa::path(
+    context_path = "/templates",
+    params(("name" = String, Path, description = "Name of the template"), AddQuery),
+    request_body = Vec<TemplateItem>,
+    responses(
+        (status = 202, description = "Products are queued for upload", body = JobAccepted),
+        (status = 200, description = "Dry run, nothing is sent or stored", body = DryRun),
+        (status = 404, description = "Template is not found", body = ErrorBody),
+        (status = 422, description = "Some items could not be built or checked", body = UploadRejected)
+    )
+)]
+#[post("/{name}/products")]
+async fn add(
+    path: web::Path<String>,
+    items: web::Json<Vec<TemplateItem>>,
+    query: web::Query<AddQuery>,
+    client: web::Data<Client>,
+) -> impl Responder {
+    match expand(&path, &items).await {
+        Ok(products) => submit(products, &query, &client).await.respond(),
+        Err(response) => response,
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::{BTreeSet, HashSet};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::entities::product::Product;
+
+/// Tags Kaspi shows in descriptions
+pub const ALLOWED_TAGS: [&str; 10] = ["p", "br", "b", "strong", "i", "em", "u", "ul", "ol", "li"];
+/// Longest description, in characters of markup
+pub const MAX_DESCRIPTION_CHARS: usize = 5000;
+
+/// What the sanitizer changed in the description of one product
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct DescriptionReport {
+    pub sku: String,
+    /// Tags dropped, keeping their text
+    pub removed_tags: Vec<String>,
+    /// Attributes, such as styles and tracking data, were dropped
+    pub removed_attributes: bool,
+    pub removed_emoji: usize,
+    pub whitespace_normalized: bool,
+    /// Length of the description before it was cut to `MAX_DESCRIPTION_CHARS`
+    pub truncated_from: Option<usize>,
+}
+
+impl DescriptionReport {
+    pub fn is_clean(&self) -> bool {
+        self.removed_tags.is_empty()
+            && !self.removed_attributes
+            && self.removed_emoji == 0
+            && !self.whitespace_normalized
+            && self.truncated_from.is_none()
+    }
+}
+
+/// Cleans the description of the product in place
+/// The report misses some fixes, such as escaping a stray `<`, so the cleaned text is always kept
+pub fn sanitize_product(product: &mut Product) -> DescriptionReport {
+    let (description, mut report) = sanitize(product.description());
+    report.sku = product.sku().to_owned();
+
+    product.set_description(description);
+    report
+}
+
+/// Keeps the allowed tags without attributes, drops emoji and extra whitespace
+/// and cuts the result to `MAX_DESCRIPTION_CHARS`
+pub fn sanitize(html: &str) -> (String, DescriptionReport) {
+    let mut report = DescriptionReport::default();
+
+    let (removed_tags, removed_attributes) = scan_tags(html);
+    report.removed_tags = removed_tags;
+    report.removed_attributes = removed_attributes;
+
+    let mut without_emoji = String::new();
+    let mut chars = html.chars().peekable();
+    while let Some(c) = chars.next() {
+        // Any symbol followed by the presentation selector is shown as emoji
+        if !is_emoji(c) && chars.peek() != Some(&'\u{FE0F}') {
+            without_emoji.push(c);
+        }
+    }
+    report.removed_emoji = html.chars().count() - without_emoji.chars().count();
+
+    let cleaned = clean(&without_emoji);
+    let normalized = normalize_whitespace(&cleaned);
+    report.whitespace_normalized = normalized != cleaned;
+
+    let length = normalized.chars().count();
+    if length <= MAX_DESCRIPTION_CHARS {
+        return (normalized, report);
+    }
+
+    report.truncated_from = Some(length);
+    let mut budget = MAX_DESCRIPTION_CHARS;
+    loop {
+        let mut cut: String = normalized.chars().take(budget).collect();
+        // Do not leave half a word or half a tag
+        if let Some(end) = cut.rfind([' ', '\n', '<']) {
+            cut.truncate(end);
+        }
+
+        // Cleaning again closes the tags left open
+        let description = normalize_whitespace(&clean(&cut));
+        let overflow = description.chars().count().saturating_sub(MAX_DESCRIPTION_CHARS);
+        if overflow == 0 {
+            return (description, report);
+        }
+        budget -= overflow;
+    }
+}
+
+fn clean(html: &str) -> String {
+    ammonia::Builder::empty()
+        .tags(HashSet::from(ALLOWED_TAGS))
+        .strip_comments(true)
+        .clean(html)
+        .to_string()
+}
+
+/// Names of the tags that are not allowed, and whether any tag has attributes
+fn scan_tags(html: &str) -> (Vec<String>, bool) {
+    let mut removed = BTreeSet::new();
+    let mut attributes = false;
+
+    for part in html.split('<').skip(1) {
+        let part = part.strip_prefix('/').unwrap_or(part);
+        let name: String = part
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == ':' || *c == '-')
+            .collect();
+        if name.is_empty() || !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            continue;
+        }
+
+        let name = name.to_ascii_lowercase();
+        if !ALLOWED_TAGS.contains(&name.as_str()) {
+            removed.insert(name.clone());
+        }
+
+        let rest = part[name.len()..].split('>').next().unwrap_or_default();
+        if rest.trim_end_matches('/').trim().contains('=') {
+            attributes = true;
+        }
+    }
+
+    (removed.into_iter().collect(), attributes)
+}
+
+/// Symbols shown as emoji by default
+/// Others of the symbol blocks, such as ★ and ✓, are text unless followed by U+FE0F
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32,
+        0x1F000..=0x1FAFF // Pictographs, emoticons, transport, flags
+        | 0x2614..=0x2615 | 0x2648..=0x2653 | 0x267F | 0x2693 | 0x26A1 | 0x26AA..=0x26AB
+        | 0x26BD..=0x26BE | 0x26C4..=0x26C5 | 0x26CE | 0x26D4 | 0x26EA | 0x26F2..=0x26F3
+        | 0x26F5 | 0x26FA | 0x26FD // Miscellaneous symbols
+        | 0x2705 | 0x270A..=0x270B | 0x2728 | 0x274C | 0x274E | 0x2753..=0x2755 | 0x2757
+        | 0x2795..=0x2797 | 0x27B0 | 0x27BF // Dingbats
+        | 0xFE0F          // Emoji presentation selector
+        | 0x200D          // Zero width joiner
+        | 0x20E3          // Keycap
+    )
+}
+
+/// One space between words, at most one empty line between paragraphs
+fn normalize_whitespace(text: &str) -> String {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n").replace('\u{a0}', " ").replace("&nbsp;", " ");
+
+    let lines: Vec<String> = text
+        .split('\n')
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .collect();
+
+    let mut result: Vec<&str> = Vec::new();
+    for line in lines.iter() {
+        if line.is_empty() && result.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        result.push(line);
+    }
+    while result.last().is_some_and(|last| last.is_empty()) {
+        result.pop();
+    }
+
+    // Spaces at the edges of a block are not shown
+    let mut text = result.join("\n");
+    for tag in ["p", "li", "ul", "ol"] {
+        text = text
+            .replace(&format!("<{}> ", tag), &format!("<{}>", tag))
+            .replace(&format!(" </{}>", tag), &format!("</{}>", tag));
+    }
+
+    text
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::product;
+
+    #[test]
+    fn word_markup() {
+        let html = "<p class=\"MsoNormal\" style=\"mso-line-height: 1\" data-track=\"42\">Парик <b>из  натуральных</b>\u{a0}волос 😍<o:p></o:p></p>\r\n\r\n\r\n<!--[if gte mso 9]><xml></xml><![endif]--><script>track()</script><ul><li>60 см</li></ul>";
+
+        let (description, report) = sanitize(html);
+
+        assert_eq!(description, "<p>Парик <b>из натуральных</b> волос</p>\n\n<ul><li>60 см</li></ul>");
+        assert_eq!(report.removed_tags, vec!["o:p", "script", "xml"]);
+        assert!(report.removed_attributes);
+        assert_eq!(report.removed_emoji, 1);
+        assert!(report.whitespace_normalized);
+        assert_eq!(report.truncated_from, None);
+    }
+
+    #[test]
+    fn clean_description_is_kept() {
+        let (description, report) = sanitize("<p>Lace front wig</p>\n<ul><li>60 cm</li></ul>");
+
+        assert_eq!(description, "<p>Lace front wig</p>\n<ul><li>60 cm</li></ul>");
+        assert!(report.is_clean());
+    }
+
+    #[test]
+    fn symbols_and_emoji() {
+        let (description, report) = sanitize("<p>★ 5.0 ✓ В наличии ✅ ❤\u{FE0F}</p>");
+
+        assert_eq!(description, "<p>★ 5.0 ✓ В наличии</p>");
+        assert_eq!(report.removed_emoji, 3);
+    }
+
+    #[test]
+    fn stray_markup_is_escaped() {
+        let mut product = product("LACEFRONT-27", "Title");
+        product.set_description(String::from("<p>Длина < 60 см</p>"));
+
+        sanitize_product(&mut product);
+
+        assert_eq!(product.description(), "<p>Длина &lt; 60 см</p>");
+    }
+
+    #[test]
+    fn length_limit() {
+        let html = format!("<p>{}</p>", "word ".repeat(MAX_DESCRIPTION_CHARS));
+
+        let (description, report) = sanitize(&html);
+
+        assert!(description.chars().count() <= MAX_DESCRIPTION_CHARS);
+        assert!(description.starts_with("<p>word") && description.ends_with("word</p>"));
+        assert_eq!(report.truncated_from, Some(html.chars().count() - 1));
+    }
+}
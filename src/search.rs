@@ -0,0 +1,161 @@
+use uuid::Uuid;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::entities::{attribute::AttributeValue, product::Product};
+
+/// Splits the text into lowercase words
+/// Cyrillic, including the Kazakh letters, is kept; `ё` is folded into `е`
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            word.chars()
+                .flat_map(char::to_lowercase)
+                .map(|c| if c == 'ё' { 'е' } else { c })
+                .collect()
+        })
+        .collect()
+}
+
+fn product_tokens(product: &Product) -> HashSet<String> {
+    let mut tokens: HashSet<String> = HashSet::new();
+
+    for text in [product.title(), product.description(), product.brand()] {
+        tokens.extend(tokenize(text));
+    }
+    for attribute in product.attributes() {
+        if let AttributeValue::String(value) = &attribute.value {
+            tokens.extend(tokenize(value));
+        }
+    }
+
+    tokens
+}
+
+/// Inverted index over the texts of the products
+#[derive(Default)]
+pub struct SearchIndex {
+    postings: BTreeMap<String, HashSet<Uuid>>,
+    documents: HashMap<Uuid, HashSet<String>>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, id: Uuid, product: &Product) {
+        self.remove(&id);
+
+        let tokens = product_tokens(product);
+        for token in tokens.iter() {
+            self.postings.entry(token.clone()).or_default().insert(id);
+        }
+        self.documents.insert(id, tokens);
+    }
+
+    pub fn remove(&mut self, id: &Uuid) {
+        let Some(tokens) = self.documents.remove(id) else {
+            return;
+        };
+
+        for token in tokens.iter() {
+            if let Some(ids) = self.postings.get_mut(token) {
+                ids.remove(id);
+                if ids.is_empty() {
+                    self.postings.remove(token);
+                }
+            }
+        }
+    }
+
+    /// Ids of the products containing a word starting with `prefix`
+    /// An exact match scores higher than a prefix one
+    fn lookup(&self, prefix: &str) -> HashMap<Uuid, usize> {
+        let mut found: HashMap<Uuid, usize> = HashMap::new();
+
+        for (token, ids) in self.postings.range(prefix.to_owned()..) {
+            if !token.starts_with(prefix) {
+                break;
+            }
+
+            let score = if token == prefix { 2 } else { 1 };
+            for id in ids.iter() {
+                let best = found.entry(*id).or_default();
+                *best = (*best).max(score);
+            }
+        }
+
+        found
+    }
+
+    /// Returns the products matching every word of the query, best first
+    pub fn search(&self, query: &str) -> Vec<(Uuid, usize)> {
+        let mut scores: Option<HashMap<Uuid, usize>> = None;
+
+        for word in tokenize(query).iter() {
+            let found = self.lookup(word);
+
+            scores = Some(match scores {
+                None => found,
+                Some(scores) => scores
+                    .into_iter()
+                    .filter_map(|(id, score)| found.get(&id).map(|s| (id, score + s)))
+                    .collect(),
+            });
+        }
+
+        let mut results: Vec<(Uuid, usize)> = scores.unwrap_or_default().into_iter().collect();
+        results.sort_by(|(a_id, a), (b_id, b)| b.cmp(a).then_with(|| a_id.cmp(b_id)));
+
+        results
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::product_json;
+
+    fn product(title: &str, description: &str) -> Product {
+        let mut product = product_json("LACEFRONT-27", title);
+        product["description"] = serde_json::json!(description);
+        product["attributes"] = serde_json::json!([
+            {
+                "code": "Wigs and hairpieces*Harakteristiki.wigs and hairpieces*purpose",
+                "value": "zhenskiy"
+            }
+        ]);
+
+        serde_json::from_value(product).unwrap()
+    }
+
+    #[test]
+    fn tokenize_cyrillic() {
+        assert_eq!(tokenize("Парик ЖЁСТКИЙ, 60см"), vec!["парик", "жесткий", "60см"]);
+        assert_eq!(tokenize("Әйелдер ҚЫЗЫЛ-шаш"), vec!["әйелдер", "қызыл", "шаш"]);
+    }
+
+    #[test]
+    fn search_and_remove() {
+        let mut index = SearchIndex::new();
+        let wig = Uuid::new_v5(&Uuid::NAMESPACE_URL, b"wig");
+        let lace = Uuid::new_v5(&Uuid::NAMESPACE_URL, b"lace");
+
+        index.insert(wig, &product("Парик женский", "Натуральный блонд"));
+        index.insert(lace, &product("Парики на сетке", "Ёлочкой"));
+
+        let found: Vec<Uuid> = index.search("ПАРИК").into_iter().map(|(id, _)| id).collect();
+        // An exact word ranks above a longer one
+        assert_eq!(found, vec![wig, lace]);
+
+        assert_eq!(index.search("парик блонд").len(), 1);
+        assert_eq!(index.search("елочкой")[0].0, lace);
+        assert_eq!(index.search("zhensk").len(), 2);
+        assert!(index.search("").is_empty());
+
+        index.remove(&wig);
+        assert_eq!(index.search("блонд"), Vec::new());
+        assert_eq!(index.search("парик").len(), 1);
+    }
+}
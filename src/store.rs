@@ -1,11 +1,13 @@
 use uuid::Uuid;
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, Mutex};
 use std::collections::HashMap;
-use serde_json::json;
+use chrono::{DateTime, Utc};
 use crate::{
-    entities::product::{Product, Record},
+    entities::{product::Product, attempt::Attempt, timestamps::Timestamps},
     entities::upload_result::{Status, UploadResult},
-    json_processing::{read_json, FILE_NAME},
+    json_processing::{read_records, quarantine, FILE_NAME},
+    events::{EventKind, StoreEvent, CHANNEL_CAPACITY},
+    search::SearchIndex,
 };
 
 
@@ -14,7 +16,17 @@ pub struct Store {
     products: Mutex<HashMap<Uuid, Product>>,
     uploaded: Mutex<HashMap<Uuid, String>>,
     finished: Mutex<HashMap<Uuid, String>>,
-    aborted: Mutex<HashMap<Uuid, String>>
+    aborted: Mutex<HashMap<Uuid, String>>,
+    timestamps: Mutex<HashMap<Uuid, Timestamps>>,
+    history: Mutex<HashMap<Uuid, Vec<Attempt>>>,
+    index: Mutex<SearchIndex>,
+    events: broadcast::Sender<StoreEvent>,
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Store {
@@ -25,56 +37,129 @@ impl Store {
             uploaded: Mutex::new(HashMap::new()),
             finished: Mutex::new(HashMap::new()),
             aborted: Mutex::new(HashMap::new()),
+            timestamps: Mutex::new(HashMap::new()),
+            history: Mutex::new(HashMap::new()),
+            index: Mutex::new(SearchIndex::new()),
+            events: broadcast::channel(CHANNEL_CAPACITY).0,
         }
     }
 
+    /// Loads the records from the data file
+    /// Entries that could not be read are moved to the quarantine file
     pub async fn fill(&self) {
-        let json = read_json(FILE_NAME).await.expect("Could not read json file");
+        let loaded = read_records(FILE_NAME).await.expect("Could not read data file");
 
-        for entry in json.into_iter() {
-            let id = Uuid::parse_str(entry["id"].as_str().unwrap()).unwrap();
+        if !loaded.rejected.is_empty() {
+            log::warn!("{} entries of {} could not be read", loaded.rejected.len(), FILE_NAME);
+            quarantine(FILE_NAME, loaded.rejected).await.expect("Could not quarantine entries");
+        }
 
-            let code = entry["code"].as_str().unwrap().to_string();
-            let has_result = !entry["result"].is_null();
+        for record in loaded.records.into_iter() {
+            let id = record.id();
+            let code = record.code().to_owned();
+            let history = record.history();
 
-            match Status::from(entry["status"].as_str().unwrap()) {
+            match record.status() {
                 Status::UPLOADED => self.uploaded.lock().await.insert(id, code),
                 Status::FINISHED => self.finished.lock().await.insert(id, code),
                 Status::ABORTED => self.aborted.lock().await.insert(id, code),
             };
 
-            let product: Product = serde_json::from_value(entry["product"].clone()).unwrap();
-            self.products.lock().await.insert(id, product);
+            if let Some(result) = record.result() {
+                self.results.lock().await.insert(id, result.clone());
+            }
 
-            if has_result {
-                let result: UploadResult = serde_json::from_value(entry["result"].clone()).unwrap();
-                self.results.lock().await.insert(id, result);
+            let mut timestamps = record.timestamps();
+            if record.status() == Status::UPLOADED && timestamps.uploaded_at.is_none() {
+                // Older files do not keep upload times, count from loading
+                timestamps.uploaded_at = history.last().and_then(|a| a.at).or_else(|| Some(Utc::now()));
             }
+
+            self.timestamps.lock().await.insert(id, timestamps);
+            self.history.lock().await.insert(id, history);
+            self.index.lock().await.insert(id, record.product());
+            self.products.lock().await.insert(id, record.product().clone());
+        }
+    }
+
+    /// Subscribes to changes of the records
+    pub fn subscribe(&self) -> broadcast::Receiver<StoreEvent> {
+        self.events.subscribe()
+    }
+
+    async fn emit(&self, kind: EventKind, id: Uuid) {
+        // Nobody may be listening
+        if self.events.receiver_count() == 0 {
+            return;
         }
+
+        let sku = self.get_product(&id).await.map(|p| p.sku().to_owned());
+        let (code, status) = self.get_status(&id).await.unzip();
+
+        let _ = self.events.send(StoreEvent { kind, id, sku, code, status });
     }
 
     /// Returns None if the code was not present
+    /// Starts a new attempt in the history of the product
     pub async fn insert_upload(&self, id: Uuid, code: String) -> Option<String> {
-        self.uploaded
+        if let Some(product) = self.get_product(&id).await {
+            let attempt = Attempt::new(code.clone(), &product);
+            self.history.lock().await.entry(id).or_default().push(attempt);
+        }
+
+        let old = self.uploaded
             .lock()
             .await
-            .insert(id, code)
+            .insert(id, code);
+        if let Some(timestamps) = self.timestamps.lock().await.get_mut(&id) {
+            timestamps.uploaded_at = Some(Utc::now());
+            timestamps.checked_at = None;
+            timestamps.finished_at = None;
+        }
+
+        self.emit(EventKind::CodeAssigned, id).await;
+        old
     }
 
     /// Returns None if the product was not present
     pub async fn insert_product(&self, id: Uuid, product: Product) -> Option<Product> {
+        self.index.lock().await.insert(id, &product);
+
+        let old = self.products
+            .lock()
+            .await
+            .insert(id, product);
+
+        if old.is_none() {
+            self.timestamps.lock().await.insert(id, Timestamps::created());
+            self.emit(EventKind::ProductAdded, id).await;
+        }
+        old
+    }
+
+    /// Removes a product that has not been uploaded yet
+    pub async fn remove_product(&self, id: &Uuid) -> Option<Product> {
+        self.timestamps.lock().await.remove(id);
+        self.index.lock().await.remove(id);
         self.products
             .lock()
             .await
-            .insert(id, product)
+            .remove(id)
     }
 
     /// Returns None if the product was not present
     pub async fn insert_result(&self, id: Uuid, result: UploadResult) -> Option<UploadResult> {
-        self.results
+        let old = self.results
             .lock()
             .await
-            .insert(id, result)
+            .insert(id, result.clone());
+
+        if let Some(attempt) = self.history.lock().await.get_mut(&id).and_then(|h| h.last_mut()) {
+            attempt.result = Some(result);
+        }
+
+        self.emit(EventKind::ResultStored, id).await;
+        old
     }
 
     /// Returns the code and the status of uploading
@@ -84,10 +169,8 @@ impl Store {
             Some((code.clone(), Status::UPLOADED))
         } else if let Some(code) = self.finished.lock().await.get(id) {
             Some((code.clone(), Status::FINISHED))
-        } else if let Some(code) = self.aborted.lock().await.get(id) {
-            Some((code.clone(), Status::ABORTED))
         } else {
-            None
+            self.aborted.lock().await.get(id).map(|code| (code.clone(), Status::ABORTED))
         }
     }
     /// Returns the product
@@ -102,21 +185,43 @@ impl Store {
     }
 
     /// Returns the code of uploading, if the id is moved successfuly
+    /// Otherwise, _None_, e.g. when another check has archived it already
     pub async fn archive(&self, id: &Uuid, status: Status) -> Option<String> {
-        let code = self.uploaded
-            .lock()
-            .await
-            .remove(id)
-            .expect("Could not remove from the store");
+        let code = self.uploaded.lock().await.remove(id)?;
+        if let Some(timestamps) = self.timestamps.lock().await.get_mut(id) {
+            timestamps.finished_at = Some(Utc::now());
+        }
 
-        let mut added: Option<String> = None;
         match status {
-            Status::FINISHED => added = self.finished.lock().await.insert(id.to_owned(), code),
-            Status::ABORTED => added = self.aborted.lock().await.insert(id.to_owned(), code),
-            _ => {}
+            Status::FINISHED => self.finished.lock().await.insert(id.to_owned(), code.clone()),
+            Status::ABORTED => self.aborted.lock().await.insert(id.to_owned(), code.clone()),
+            _ => None,
+        };
+
+        if let Some(attempt) = self.history.lock().await.get_mut(id).and_then(|h| h.last_mut()) {
+            attempt.status = status;
         }
 
-        added
+        self.emit(EventKind::Archived, id.to_owned()).await;
+        Some(code)
+    }
+
+    /// Takes an archived product back, so it can be uploaded again
+    /// Returns the code of the archived upload
+    pub async fn reopen(&self, id: &Uuid) -> Option<String> {
+        self.results.lock().await.remove(id);
+        let aborted = self.aborted.lock().await.remove(id);
+        aborted.or(self.finished.lock().await.remove(id))
+    }
+
+    /// Returns the ids of the products matching the query with their scores, best first
+    pub async fn search(&self, query: &str) -> Vec<(Uuid, usize)> {
+        self.index.lock().await.search(query)
+    }
+
+    /// Returns the attempts to upload the product, oldest first
+    pub async fn get_history(&self, id: &Uuid) -> Vec<Attempt> {
+        self.history.lock().await.get(id).cloned().unwrap_or_default()
     }
 
     pub async fn uploaded_len(&self) -> usize {
@@ -131,6 +236,83 @@ impl Store {
         self.aborted.lock().await.len()
     }
 
+    /// Marks the status of the upload as checked with Kaspi
+    pub async fn touch_checked(&self, id: &Uuid) {
+        if let Some(timestamps) = self.timestamps.lock().await.get_mut(id) {
+            timestamps.checked_at = Some(Utc::now());
+        }
+    }
+
+    /// Returns the lifecycle of the record
+    pub async fn get_timestamps(&self, id: &Uuid) -> Option<Timestamps> {
+        self.timestamps.lock().await.get(id).cloned()
+    }
+
+    /// Returns the time the oldest pending code was received
+    pub async fn oldest_uploaded_at(&self) -> Option<DateTime<Utc>> {
+        let ids = self.uploaded_ids().await;
+        let timestamps = self.timestamps.lock().await;
+
+        ids.iter()
+            .filter_map(|id| timestamps.get(id).and_then(|t| t.uploaded_at))
+            .min()
+    }
+
+    /// Returns the newest version of every SKU that has been uploaded
+    pub async fn latest_versions(&self) -> Vec<(Uuid, Product)> {
+        self.latest(|_, _| true).await
+    }
+
+    /// Returns the newest version of every SKU that Kaspi has received
+    pub async fn latest_versions_on_kaspi(&self) -> Vec<(Uuid, Product)> {
+        self.latest(|_, status| status != Status::ABORTED).await
+    }
+
+    /// Returns the newest version of the SKU that Kaspi has received
+    /// Aborted versions are not on Kaspi
+    pub async fn latest_on_kaspi(&self, sku: &str) -> Option<(Uuid, Product)> {
+        self.latest(|product, status| product.sku() == sku && status != Status::ABORTED)
+            .await
+            .pop()
+    }
+
+    /// Most recently uploaded version of every SKU among the matching ones
+    /// Retried versions count from their last upload
+    async fn latest(&self, matches: impl Fn(&Product, Status) -> bool) -> Vec<(Uuid, Product)> {
+        let mut latest: HashMap<String, (Option<DateTime<Utc>>, Uuid, Product)> = HashMap::new();
+
+        for (id, product, _, status) in self.uploaded_products().await {
+            if !matches(&product, status) {
+                continue;
+            }
+            let uploaded_at = self.get_timestamps(&id).await.and_then(|t| t.uploaded_at.or(t.created_at));
+
+            match latest.get(product.sku()) {
+                Some((newest, _, _)) if *newest >= uploaded_at => {}
+                _ => {
+                    latest.insert(product.sku().to_owned(), (uploaded_at, id, product));
+                }
+            }
+        }
+
+        latest.into_values().map(|(_, id, product)| (id, product)).collect()
+    }
+
+    /// Returns the products with their codes and statuses
+    /// Products still being uploaded have no code yet and are left out
+    pub async fn uploaded_products(&self) -> Vec<(Uuid, Product, String, Status)> {
+        let products = self.products.lock().await.clone();
+        let mut uploaded = Vec::new();
+
+        for (id, product) in products {
+            if let Some((code, status)) = self.get_status(&id).await {
+                uploaded.push((id, product, code, status));
+            }
+        }
+
+        uploaded
+    }
+
     pub async fn uploaded_ids(&self) -> Vec<Uuid> {
         self.uploaded.lock().await.keys().cloned().collect()
     }
@@ -144,6 +326,7 @@ impl Store {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::product;
 
     #[actix_rt::test]
     async fn insert_get_remove() {
@@ -159,10 +342,64 @@ mod tests {
 
         assert!(store.insert_upload(other_id, other_code).await.is_some());
 
-        assert_eq!(store.get_status(id).await.unwrap(), (code, Status::UPLOADED));
+        assert_eq!(store.get_status(&id).await.unwrap(), (code.clone(), Status::UPLOADED));
         assert_eq!(store.uploaded_len().await, 1);
 
-        store.archive(id, Status::FINISHED).await;
+        assert_eq!(store.archive(&id, Status::FINISHED).await, Some(code));
         assert_eq!(store.uploaded_len().await, 0);
+
+        // Another check of the same upload finds nothing to move
+        assert_eq!(store.archive(&id, Status::FINISHED).await, None);
+        assert_eq!(store.finished_len().await, 1);
+    }
+
+    #[actix_rt::test]
+    async fn history_of_attempts() {
+        let store = Store::new();
+
+        let product = product("LACEFRONT-27", "Title");
+        let id = Uuid::new_v5(&uuid::Uuid::NAMESPACE_URL, b"LACEFRONT-27");
+
+        store.insert_product(id, product).await;
+        store.insert_upload(id, String::from("0000001")).await;
+        store.archive(&id, Status::ABORTED).await;
+        store.insert_result(id, UploadResult::new(1, 0, 0, 1, Vec::new())).await;
+
+        assert_eq!(store.reopen(&id).await, Some(String::from("0000001")));
+        store.insert_upload(id, String::from("0000002")).await;
+
+        let history = store.get_history(&id).await;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].status, Status::ABORTED);
+        assert_eq!(history[0].result, Some(UploadResult::new(1, 0, 0, 1, Vec::new())));
+        assert_eq!((history[1].code.as_str(), history[1].status), ("0000002", Status::UPLOADED));
+        assert_eq!(history[0].product_hash, history[1].product_hash);
+    }
+
+    #[actix_rt::test]
+    async fn latest_version_on_kaspi() {
+        let store = Store::new();
+        let product = |title: &str| product("LACEFRONT-27", title);
+
+        // A is replaced on Kaspi by B, then an upload of C is aborted
+        for (i, (title, status)) in [("A", Status::FINISHED), ("B", Status::FINISHED), ("C", Status::ABORTED)].into_iter().enumerate() {
+            let id = Uuid::from_u128(i as u128 + 1);
+            store.insert_product(id, product(title)).await;
+            store.insert_upload(id, format!("000000{}", i)).await;
+            store.archive(&id, status).await;
+        }
+
+        assert_eq!(store.latest_on_kaspi("LACEFRONT-27").await, Some((Uuid::from_u128(2), product("B"))));
+        assert_eq!(store.latest_versions().await, vec![(Uuid::from_u128(3), product("C"))]);
+        assert_eq!(store.latest_versions_on_kaspi().await, vec![(Uuid::from_u128(2), product("B"))]);
+        assert_eq!(store.latest_on_kaspi("OTHER").await, None);
+
+        // Going back to A makes it the newest, though it was created first
+        let a = Uuid::from_u128(1);
+        assert_eq!(store.reopen(&a).await, Some(String::from("0000000")));
+        store.insert_upload(a, String::from("0000003")).await;
+        store.archive(&a, Status::FINISHED).await;
+
+        assert_eq!(store.latest_on_kaspi("LACEFRONT-27").await, Some((a, product("A"))));
     }
 }
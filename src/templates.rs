@@ -0,0 +1,293 @@
+use std::collections::{BTreeMap, HashMap};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use tokio::sync::Mutex;
+use crate::{
+    translit::{header_code, value_code},
+    entities::{attribute::{Attribute, AttributeValue}, product::Product},
+    json_processing::{read_json, save_json},
+};
+
+pub const TEMPLATES_FILE: &str = "templates.json";
+
+/// Fields shared by the products of one kind
+/// The description may hold placeholders such as `{title}`, filled from the item
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct Template {
+    /// Taken from the path when the template is stored
+    #[serde(default)]
+    pub name: String,
+    pub category: String,
+    pub brand: String,
+    #[serde(default)]
+    pub attributes: Vec<Attribute>,
+    pub description: String,
+    #[serde(default)]
+    pub images: Vec<String>,
+}
+
+/// What an item changes in its template
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct Overrides {
+    pub brand: Option<String>,
+    pub description: Option<String>,
+    /// Replace the template attributes with the same code, the rest are added
+    #[serde(default)]
+    pub attributes: Vec<Attribute>,
+    /// Attribute values typed by hand under spreadsheet headers, such as "Длина" or "Характеристики: Длина"
+    /// Codes are built from the header and the category, values are transliterated
+    /// The ones in `attributes` take precedence
+    #[serde(default)]
+    pub attributes_by_header: BTreeMap<String, String>,
+    /// Replace the template images when given
+    pub images: Option<Vec<String>>,
+    /// Values of the placeholders in the description
+    #[serde(default)]
+    pub values: BTreeMap<String, String>,
+}
+
+/// Compact product built from a template
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct TemplateItem {
+    pub sku: String,
+    pub title: String,
+    #[serde(default)]
+    pub overrides: Overrides,
+}
+
+impl Template {
+    /// Builds the full product of the item
+    /// Fails if a placeholder of the description has no value
+    pub fn expand(&self, item: &TemplateItem) -> Result<Product, String> {
+        let overrides = &item.overrides;
+        let brand = overrides.brand.clone().unwrap_or_else(|| self.brand.clone());
+
+        let mut values = overrides.values.clone();
+        values.insert(String::from("sku"), item.sku.clone());
+        values.insert(String::from("title"), item.title.clone());
+        values.insert(String::from("brand"), brand.clone());
+        values.insert(String::from("category"), self.category.clone());
+
+        let description = fill(overrides.description.as_ref().unwrap_or(&self.description), &values)?;
+
+        let mut item_attributes: Vec<Attribute> = overrides.attributes_by_header
+            .iter()
+            .map(|(header, value)| Attribute {
+                code: header_code(&self.category, header),
+                value: AttributeValue::String(value_code(value)),
+            })
+            .filter(|a| !overrides.attributes.iter().any(|o| o.code == a.code))
+            .collect();
+        item_attributes.extend(overrides.attributes.iter().cloned());
+
+        let mut attributes: Vec<Attribute> = self.attributes
+            .iter()
+            .filter(|a| !item_attributes.iter().any(|o| o.code == a.code))
+            .cloned()
+            .collect();
+        attributes.extend(item_attributes);
+
+        Ok(Product::new(
+            item.sku.clone(),
+            item.title.clone(),
+            brand,
+            self.category.clone(),
+            description,
+            attributes,
+            overrides.images.clone().unwrap_or_else(|| self.images.clone()),
+        ))
+    }
+}
+
+/// Replaces every `{name}` with its value
+fn fill(skeleton: &str, values: &BTreeMap<String, String>) -> Result<String, String> {
+    let mut result = String::with_capacity(skeleton.len());
+    let mut rest = skeleton;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        let name = after
+            .find('}')
+            .map(|end| &after[..end])
+            .filter(|name| !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_'));
+
+        match name {
+            Some(name) => {
+                let value = values.get(name).ok_or_else(|| format!("Placeholder {{{}}} has no value", name))?;
+                result.push_str(value);
+                rest = &after[name.len() + 1..];
+            }
+            // Not a placeholder
+            None => {
+                result.push('{');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+pub struct Templates {
+    templates: Mutex<HashMap<String, Template>>,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Templates {
+    pub fn new() -> Self {
+        Self { templates: Mutex::new(HashMap::new()) }
+    }
+
+    pub async fn fill(&self) {
+        let templates = read_json(TEMPLATES_FILE).await.expect("Could not read templates file");
+        *self.templates.lock().await = templates
+            .into_iter()
+            .map(|t| serde_json::from_value::<Template>(t).expect("Could not parse template"))
+            .map(|t| (t.name.clone(), t))
+            .collect();
+    }
+
+    pub async fn get(&self, name: &str) -> Option<Template> {
+        self.templates.lock().await.get(name).cloned()
+    }
+
+    /// Sorted by name
+    pub async fn all(&self) -> Vec<Template> {
+        sorted(&*self.templates.lock().await)
+    }
+
+    /// Returns the replaced template
+    pub async fn insert(&self, template: Template) -> Option<Template> {
+        let mut templates = self.templates.lock().await;
+        let old = templates.insert(template.name.clone(), template);
+        save(&templates).await;
+        old
+    }
+
+    pub async fn remove(&self, name: &str) -> Option<Template> {
+        let mut templates = self.templates.lock().await;
+        let old = templates.remove(name);
+        if old.is_some() {
+            save(&templates).await;
+        }
+        old
+    }
+}
+
+fn sorted(templates: &HashMap<String, Template>) -> Vec<Template> {
+    let mut templates: Vec<Template> = templates.values().cloned().collect();
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    templates
+}
+
+/// Takes the locked templates, a concurrent change waits until the file is written
+async fn save(templates: &HashMap<String, Template>) {
+    let json = serde_json::to_value(sorted(templates)).expect("Could not create Value");
+    if let Err(e) = save_json(TEMPLATES_FILE, json).await {
+        log::error!("Could not save templates: {}", e);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn template() -> Template {
+        serde_json::from_value(json!({
+            "name": "lace-front",
+            "category": "Wigs and hairpieces",
+            "brand": "ParikiAlmaty",
+            "attributes": [
+                { "code": "purpose", "value": "zhenskiy" },
+                { "code": "color", "value": "black" }
+            ],
+            "description": "{title} by {brand}, {length} cm. {sku}",
+            "images": ["https://a.kz/1.jpg"]
+        })).unwrap()
+    }
+
+    #[test]
+    fn expand_item() {
+        let item: TemplateItem = serde_json::from_value(json!({
+            "sku": "LACEFRONT-27",
+            "title": "Lace front wig",
+            "overrides": {
+                "attributes": [{ "code": "color", "value": "red" }],
+                "values": { "length": "60" }
+            }
+        })).unwrap();
+
+        let product = template().expand(&item).unwrap();
+
+        assert_eq!(json!(product), json!({
+            "sku": "LACEFRONT-27",
+            "title": "Lace front wig",
+            "brand": "ParikiAlmaty",
+            "category": "Wigs and hairpieces",
+            "description": "Lace front wig by ParikiAlmaty, 60 cm. LACEFRONT-27",
+            "attributes": [
+                { "code": "purpose", "value": "zhenskiy" },
+                { "code": "color", "value": "red" }
+            ],
+            "images": [{ "url": "https://a.kz/1.jpg" }]
+        }));
+    }
+
+    #[test]
+    fn attributes_by_header() {
+        let purpose = "Wigs and hairpieces*Harakteristiki.wigs and hairpieces*purpose";
+        let mut template = template();
+        template.attributes[0].code = purpose.to_owned();
+
+        let item: TemplateItem = serde_json::from_value(json!({
+            "sku": "LACEFRONT-27",
+            "title": "Lace front wig",
+            "overrides": {
+                "attributes_by_header": {
+                    "purpose": "Унисекс",
+                    "Характеристики: Материал": "Натуральные  волосы",
+                    "color": "Красный"
+                },
+                "attributes": [{ "code": "Wigs and hairpieces*Harakteristiki.wigs and hairpieces*color", "value": "red" }],
+                "values": { "length": "60" }
+            }
+        })).unwrap();
+
+        let product = template.expand(&item).unwrap();
+
+        assert_eq!(json!(product)["attributes"], json!([
+            { "code": "color", "value": "black" },
+            { "code": purpose, "value": "uniseks" },
+            { "code": "Wigs and hairpieces*Harakteristiki.wigs and hairpieces*material", "value": "naturalnye volosy" },
+            { "code": "Wigs and hairpieces*Harakteristiki.wigs and hairpieces*color", "value": "red" }
+        ]));
+    }
+
+    #[test]
+    fn name_is_optional() {
+        let mut body = json!(template());
+        body.as_object_mut().unwrap().remove("name");
+
+        let template: Template = serde_json::from_value(body).unwrap();
+        assert_eq!(template.name, "");
+    }
+
+    #[test]
+    fn placeholders() {
+        let values = BTreeMap::from([(String::from("a"), String::from("1"))]);
+
+        assert_eq!(fill("{a} {not a placeholder} {}", &values).unwrap(), "1 {not a placeholder} {}");
+        assert_eq!(fill("{a", &values).unwrap(), "{a");
+        assert!(fill("{b}", &values).unwrap_err().contains("{b}"));
+    }
+}
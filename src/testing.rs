@@ -0,0 +1,45 @@
+use actix_web::{dev::ServerHandle, web::ServiceConfig, App, HttpServer};
+use crate::entities::product::Product;
+
+/// PNG signature and header of an image of the given size
+pub fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut bytes = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+    bytes.extend(width.to_be_bytes());
+    bytes.extend(height.to_be_bytes());
+    bytes.extend([8, 6, 0, 0, 0, 0, 0, 0, 0]);
+    bytes
+}
+
+/// Serves the routes on a free local port
+/// Returns the base URL and the handle that stops the server
+pub fn serve(routes: impl Fn(&mut ServiceConfig) + Send + Clone + 'static) -> (String, ServerHandle) {
+    let server = HttpServer::new(move || App::new().configure(routes.clone()))
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+    let address = server.addrs()[0];
+
+    let server = server.run();
+    let handle = server.handle();
+    actix_rt::spawn(server);
+
+    (format!("http://{}", address), handle)
+}
+
+/// JSON of a product with no attributes and images
+pub fn product_json(sku: &str, title: &str) -> serde_json::Value {
+    serde_json::json!({
+        "sku": sku,
+        "title": title,
+        "brand": "ParikiAlmaty",
+        "category": "Pariki",
+        "description": "description",
+        "attributes": [],
+        "images": []
+    })
+}
+
+/// Product with no attributes and images
+pub fn product(sku: &str, title: &str) -> Product {
+    serde_json::from_value(product_json(sku, title)).unwrap()
+}
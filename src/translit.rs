@@ -0,0 +1,104 @@
+/// Group of the attributes shown on the product page
+pub const DEFAULT_GROUP: &str = "Характеристики";
+
+/// Latin spelling of a lowercase Russian or Kazakh letter in Kaspi's scheme
+fn latin(c: char) -> Option<&'static str> {
+    let spelling = match c {
+        'а' => "a", 'б' => "b", 'в' => "v", 'г' => "g", 'д' => "d", 'е' | 'ё' => "e",
+        'ж' => "zh", 'з' => "z", 'и' => "i", 'й' => "y", 'к' => "k", 'л' => "l",
+        'м' => "m", 'н' => "n", 'о' => "o", 'п' => "p", 'р' => "r", 'с' => "s",
+        'т' => "t", 'у' => "u", 'ф' => "f", 'х' => "h", 'ц' => "ts", 'ч' => "ch",
+        'ш' => "sh", 'щ' => "sch", 'ъ' | 'ь' => "", 'ы' => "y", 'э' => "e",
+        'ю' => "yu", 'я' => "ya",
+        // Kazakh
+        'ә' => "a", 'ғ' => "g", 'қ' => "k", 'ң' => "n", 'ө' => "o", 'ұ' | 'ү' => "u",
+        'һ' => "h", 'і' => "i",
+        _ => return None,
+    };
+
+    Some(spelling)
+}
+
+/// Spells Cyrillic letters with Latin ones, keeping the case and every other character
+/// "Характеристики" becomes "Harakteristiki"
+pub fn transliterate(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        let lower = c.to_lowercase().next().unwrap_or(c);
+
+        match latin(lower) {
+            Some(spelling) if lower != c => {
+                let mut letters = spelling.chars();
+                if let Some(first) = letters.next() {
+                    result.extend(first.to_uppercase());
+                    result.extend(letters);
+                }
+            }
+            Some(spelling) => result.push_str(spelling),
+            None => result.push(c),
+        }
+    }
+
+    result
+}
+
+/// Code of an enumerated value typed by hand, such as "zhenskiy" for "Женский"
+pub fn value_code(text: &str) -> String {
+    transliterate(&words(text).to_lowercase())
+}
+
+/// Code of an attribute of the category, such as
+/// "Wigs and hairpieces*Harakteristiki.wigs and hairpieces*purpose"
+pub fn attribute_code(category: &str, group: &str, name: &str) -> String {
+    let category = words(category);
+
+    format!(
+        "{}*{}.{}*{}",
+        category,
+        transliterate(&words(group)),
+        category.to_lowercase(),
+        transliterate(&words(name).to_lowercase()),
+    )
+}
+
+/// Code of the attribute named by a column header
+/// Headers are either "group: name" or a name in the default group
+pub fn header_code(category: &str, header: &str) -> String {
+    match header.split_once(':') {
+        Some((group, name)) => attribute_code(category, group, name),
+        None => attribute_code(category, DEFAULT_GROUP, header),
+    }
+}
+
+fn words(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn russian_and_kazakh() {
+        assert_eq!(transliterate("Характеристики"), "Harakteristiki");
+        assert_eq!(transliterate("Женский парик"), "Zhenskiy parik");
+        assert_eq!(transliterate("Шаш үлгісі, қара"), "Shash ulgisi, kara");
+        assert_eq!(transliterate("Lace front 27"), "Lace front 27");
+    }
+
+    #[test]
+    fn codes() {
+        assert_eq!(value_code("  Женский "), "zhenskiy");
+        assert_eq!(value_code("Натуральные  волосы"), "naturalnye volosy");
+        assert_eq!(
+            header_code("Wigs and hairpieces", "purpose"),
+            "Wigs and hairpieces*Harakteristiki.wigs and hairpieces*purpose"
+        );
+        assert_eq!(
+            header_code("Wigs and hairpieces", "Характеристики: Длина"),
+            "Wigs and hairpieces*Harakteristiki.wigs and hairpieces*dlina"
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use uuid::Uuid;
+use std::time::Duration;
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::sync::Mutex;
+use crate::{
+    STORE,
+    entities::upload_result::{Status, UploadResult},
+    json_processing::{read_json, save_json},
+};
+
+pub const ENDPOINTS_FILE: &str = "webhooks.json";
+pub const QUEUE_FILE: &str = "webhooks_queue.json";
+pub const SIGNATURE_HEADER: &str = "X-Kaspi-Service-Signature";
+pub const MAX_ATTEMPTS: u32 = 10;
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+/// Slow endpoints would hold up the other deliveries
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookEvent {
+    FINISHED,
+    ABORTED,
+    /// Any archived import whose result has errors
+    ERRORS,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    url: String,
+    secret: Option<String>,
+    events: Vec<WebhookEvent>,
+}
+
+impl Endpoint {
+    pub fn wants(&self, payload: &Payload) -> bool {
+        let errors = payload.result.as_ref().is_some_and(|r| r.errors() > 0);
+
+        self.events.iter().any(|event| match event {
+            WebhookEvent::FINISHED => payload.status == Status::FINISHED,
+            WebhookEvent::ABORTED => payload.status == Status::ABORTED,
+            WebhookEvent::ERRORS => errors,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    id: Uuid,
+    sku: String,
+    code: String,
+    status: Status,
+    result: Option<UploadResult>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Delivery {
+    /// Queues saved before ids were kept get new ones
+    #[serde(default = "Uuid::new_v4")]
+    id: Uuid,
+    endpoint: Endpoint,
+    payload: Payload,
+    attempts: u32,
+}
+
+/// Hex encoded HMAC-SHA256 of the body
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+
+    hex::encode(mac.finalize().into_bytes())
+}
+
+pub struct Webhooks {
+    // Kaspi client carries the API token, so deliveries use their own
+    client: Client,
+    endpoints: Mutex<Vec<Endpoint>>,
+    pending: Mutex<Vec<Delivery>>,
+}
+
+impl Default for Webhooks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Webhooks {
+    pub fn new() -> Self {
+        Self {
+            client: Client::builder()
+                .timeout(DELIVERY_TIMEOUT)
+                .build()
+                .expect("Could not create webhook client"),
+            endpoints: Mutex::new(Vec::new()),
+            pending: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Loads the endpoints and the deliveries that are still to be retried
+    pub async fn fill(&self) {
+        let endpoints = read_json(ENDPOINTS_FILE).await.expect("Could not read webhooks file");
+        *self.endpoints.lock().await = endpoints
+            .into_iter()
+            .map(|e| serde_json::from_value(e).expect("Could not parse webhook endpoint"))
+            .collect();
+
+        let pending = read_json(QUEUE_FILE).await.expect("Could not read webhooks queue");
+        *self.pending.lock().await = pending
+            .into_iter()
+            .filter_map(|d| serde_json::from_value(d).ok())
+            .collect();
+    }
+
+    pub async fn endpoints_len(&self) -> usize {
+        self.endpoints.lock().await.len()
+    }
+
+    pub async fn pending_len(&self) -> usize {
+        self.pending.lock().await.len()
+    }
+
+    /// Notifies the endpoints interested in the archived import
+    pub async fn notify(&self, id: &Uuid) {
+        let (code, status) = match STORE.get_status(id).await {
+            Some(s) => s,
+            None => return,
+        };
+        let sku = STORE.get_product(id).await.map(|p| p.sku().to_owned()).unwrap_or_default();
+        let result = STORE.get_result(id).await;
+
+        let payload = Payload { id: id.to_owned(), sku, code, status, result };
+
+        let endpoints: Vec<Endpoint> = self.endpoints
+            .lock()
+            .await
+            .iter()
+            .filter(|e| e.wants(&payload))
+            .cloned()
+            .collect();
+
+        let mut failed = Vec::new();
+        for endpoint in endpoints.into_iter() {
+            let delivery = Delivery { id: Uuid::new_v4(), endpoint, payload: payload.clone(), attempts: 0 };
+
+            if let Err(delivery) = self.deliver(delivery).await {
+                failed.push(delivery);
+            }
+        }
+
+        if !failed.is_empty() {
+            let mut pending = self.pending.lock().await;
+            pending.extend(failed);
+            save(&pending).await;
+        }
+    }
+
+    async fn deliver(&self, mut delivery: Delivery) -> Result<(), Delivery> {
+        let body = serde_json::to_vec(&delivery.payload).expect("Could not create json");
+
+        let mut request = self.client
+            .post(&delivery.endpoint.url)
+            .header("Content-Type", "application/json");
+        if let Some(secret) = &delivery.endpoint.secret {
+            request = request.header(SIGNATURE_HEADER, format!("sha256={}", sign(secret, &body)));
+        }
+
+        delivery.attempts += 1;
+        match request.body(body).send().await {
+            Ok(response) if response.status().is_success() => Ok(()),
+            Ok(response) => {
+                log::warn!("Webhook {} responded with {}", delivery.endpoint.url, response.status());
+                Err(delivery)
+            }
+            Err(e) => {
+                log::warn!("Webhook {} failed: {}", delivery.endpoint.url, e);
+                Err(delivery)
+            }
+        }
+    }
+
+    /// Tries the failed deliveries once more
+    /// They stay pending while in flight, so a save in the meantime keeps them
+    pub async fn retry(&self) {
+        let pending = self.pending.lock().await.clone();
+        if pending.is_empty() {
+            return;
+        }
+
+        let mut done = Vec::new();
+        let mut failed = Vec::new();
+        for delivery in pending.into_iter() {
+            let id = delivery.id;
+            match self.deliver(delivery).await {
+                Ok(()) => done.push(id),
+                Err(delivery) if delivery.attempts >= MAX_ATTEMPTS => {
+                    log::error!("Dropping webhook {} for {} after {} attempts",
+                        delivery.endpoint.url, delivery.payload.id, delivery.attempts);
+                    done.push(id);
+                }
+                Err(delivery) => failed.push(delivery),
+            }
+        }
+
+        let mut pending = self.pending.lock().await;
+        pending.retain(|d| !done.contains(&d.id));
+        for delivery in pending.iter_mut() {
+            if let Some(attempted) = failed.iter().find(|f| f.id == delivery.id) {
+                delivery.attempts = attempted.attempts;
+            }
+        }
+        save(&pending).await;
+    }
+
+    /// Retries failed deliveries on an interval
+    pub fn spawn_retries(&'static self) {
+        actix_rt::spawn(async move {
+            let mut interval = actix_rt::time::interval(RETRY_INTERVAL);
+
+            loop {
+                interval.tick().await;
+                self.retry().await;
+            }
+        });
+    }
+}
+
+/// Called with the queue locked, so concurrent saves can not write an older queue last
+async fn save(pending: &[Delivery]) {
+    let json = serde_json::to_value(pending).expect("Could not create Value");
+    save_json(QUEUE_FILE, json).await.expect("Could not save webhooks queue");
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(status: Status, errors: usize) -> Payload {
+        Payload {
+            id: Uuid::new_v5(&Uuid::NAMESPACE_URL, b"LACEFRONT-27"),
+            sku: String::from("LACEFRONT-27"),
+            code: String::from("0000001"),
+            status,
+            result: Some(UploadResult::new(errors, 0, 0, 1, Vec::new())),
+        }
+    }
+
+    #[test]
+    fn endpoint_filters() {
+        let endpoint: Endpoint = serde_json::from_value(serde_json::json!({
+            "url": "http://localhost:9000/hook",
+            "secret": null,
+            "events": ["ABORTED", "ERRORS"]
+        })).unwrap();
+
+        assert!(!endpoint.wants(&payload(Status::FINISHED, 0)));
+        assert!(endpoint.wants(&payload(Status::FINISHED, 2)));
+        assert!(endpoint.wants(&payload(Status::ABORTED, 0)));
+    }
+
+    #[test]
+    fn hmac_signature() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}